@@ -0,0 +1,26 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+//! Signature helpers shared by the token contracts' `permit`.
+//!
+//! Recovery runs inside the contract, so every contract using it carries the
+//! secp256k1 code; keep its users to the ones that need signed approvals.
+
+use ink_env::hash::Blake2x256;
+
+/// Account id (as raw bytes) of the key that produced the recoverable ECDSA `signature`
+/// over `message_hash`: the blake2-256 hash of its compressed public key. The last
+/// signature byte is the recovery id, either 0/1 or 27/28. `None` if it does not recover.
+pub fn recover_signer(signature: &[u8; 65], message_hash: &[u8; 32]) -> Option<[u8; 32]> {
+    let mut rs = [0u8; 64];
+    rs.copy_from_slice(&signature[..64]);
+    let v = if signature[64] >= 27 { signature[64] - 27 } else { signature[64] };
+
+    let message = secp256k1::Message::parse(message_hash);
+    let sig = secp256k1::Signature::parse(&rs);
+    let recovery_id = secp256k1::RecoveryId::parse(v).ok()?;
+    let pub_key = secp256k1::recover(&message, &sig, &recovery_id).ok()?;
+
+    let mut signer = [0u8; 32];
+    ink_env::hash_bytes::<Blake2x256>(&pub_key.serialize_compressed(), &mut signer);
+    Some(signer)
+}
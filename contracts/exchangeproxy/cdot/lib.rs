@@ -3,9 +3,14 @@
 pub use self::pat::PAT;
 use ink_lang as ink;
 
+/// cDOT, native DOT wrapped 1:1 into a PAT token.
+///
+/// Other contracts also depend on this crate to call any PAT token, so the
+/// message selectors must stay those of the PAT standard.
 #[ink::contract]
 mod pat {
     use ink_prelude::string::String;
+    use ink_storage::collections::HashMap as StorageHashMap;
 
     /// The PAT error types.
     #[derive(Debug, PartialEq, Eq, scale::Encode, scale::Decode)]
@@ -16,202 +21,261 @@ mod pat {
         InsufficientSupply,
         /// Returned if not enough allowance to fulfill a request is available.
         InsufficientAllowance,
+        /// Returned by `mint` and `burn`; cDOT supply only changes through
+        /// `deposit` and `withdraw`.
+        Unsupported,
+        /// Returned if a deposit would overflow a balance or the total supply.
+        Overflow,
     }
 
     /// The PAT result type.
     pub type Result<T> = core::result::Result<T, Error>;
 
     #[ink(storage)]
-    pub struct PAT {}
+    pub struct PAT {
+        /// Total token supply, equal to the native balance held for it.
+        total_supply: Balance,
+        /// Mapping from owner to number of owned token.
+        balances: StorageHashMap<AccountId, Balance>,
+        /// Mapping of the token amount which an account is allowed to withdraw
+        /// from another account.
+        allowances: StorageHashMap<(AccountId, AccountId), Balance>,
+        name: Option<String>,
+        symbol: Option<String>,
+        decimals: Option<u8>,
+    }
+
+    /// Event emitted when a token transfer occurs.
+    #[ink(event)]
+    pub struct Transfer {
+        #[ink(topic)]
+        from: Option<AccountId>,
+        #[ink(topic)]
+        to: Option<AccountId>,
+        #[ink(topic)]
+        value: Balance,
+    }
+
+    /// Event emitted when an approval occurs that `spender` is allowed to withdraw
+    /// up to the amount of `value` tokens from `owner`.
+    #[ink(event)]
+    pub struct Approval {
+        #[ink(topic)]
+        owner: AccountId,
+        #[ink(topic)]
+        spender: AccountId,
+        #[ink(topic)]
+        value: Balance,
+    }
+
+    /// Event emitted when native DOT is wrapped.
+    #[ink(event)]
+    pub struct Deposit {
+        #[ink(topic)]
+        owner: AccountId,
+        value: Balance,
+    }
+
+    /// Event emitted when cDOT is unwrapped back to native DOT.
+    #[ink(event)]
+    pub struct Withdrawal {
+        #[ink(topic)]
+        owner: AccountId,
+        value: Balance,
+    }
 
     impl PAT {
-        /// Creates a new PAT contract with the specified initial supply.
+        /// Creates a cDOT contract with no supply; tokens only come from `deposit`.
         #[ink(constructor)]
         pub fn new(
-            _initial_supply: Balance,
-            _name: Option<String>,
-            _symbol: Option<String>,
-            _decimals: Option<u8>,
+            name: Option<String>,
+            symbol: Option<String>,
+            decimals: Option<u8>,
         ) -> Self {
-            unimplemented!()
+            Self {
+                total_supply: 0,
+                balances: StorageHashMap::new(),
+                allowances: StorageHashMap::new(),
+                name,
+                symbol,
+                decimals,
+            }
         }
 
-        // /// Returns the token name.
-        // #[ink(message, selector = "0x6b1bb951")]
-        // pub fn token_name(&self) -> Option<String> {
-        //     unimplemented!()
-        // }
-        //
-        // /// Returns the token symbol.
-        // #[ink(message, selector = "0xb42c3368")]
-        // pub fn token_symbol(&self) -> Option<String> {
-        //     unimplemented!()
-        // }
-        //
-        // /// Returns the token decimals.
-        // #[ink(message, selector = "0xc64b0eb2")]
-        // pub fn token_decimals(&self) -> Option<u8> {
-        //     unimplemented!()
-        // }
-        //
-        // /// Returns the total token supply.
-        // #[ink(message, selector = "0x143862ae")]
-        // pub fn total_supply(&self) -> Balance {
-        //     unimplemented!()
-        // }
-        //
-        // /// Returns the account balance for the specified `owner`.
-        // ///
-        // /// Returns `0` if the account is non-existent.
-        // #[ink(message, selector = "0xb7d968c9")]
-        // pub fn balance_of(&self, _owner: AccountId) -> Balance {
-        //     unimplemented!()
-        // }
-        //
-        // /// Transfers `value` amount of tokens from the caller's account to account `to`.
-        // ///
-        // /// On success a `Transfer` event is emitted.
-        // ///
-        // /// # Errors
-        // ///
-        // /// Returns `InsufficientBalance` error if there are not enough tokens on
-        // /// the caller's account balance.
-        // #[ink(message, selector = "0x10d455c2")]
-        // pub fn transfer(&mut self, _to: AccountId, _value: Balance) -> Result<()> {
-        //     unimplemented!()
-        // }
-        //
-        // /// Returns the amount which `spender` is still allowed to withdraw from `owner`.
-        // ///
-        // /// Returns `0` if no allowance has been set `0`.
-        // #[ink(message, selector = "0xc04aa300")]
-        // pub fn allowance(&self, _owner: AccountId, _spender: AccountId) -> Balance {
-        //     unimplemented!()
-        // }
-        //
-        // /// Transfers `value` tokens on the behalf of `from` to the account `to`.
-        // ///
-        // /// This can be used to allow a contract to transfer tokens on ones behalf and/or
-        // /// to charge fees in sub-currencies, for example.
-        // ///
-        // /// On success a `Transfer` event is emitted.
-        // ///
-        // /// # Errors
-        // ///
-        // /// Returns `InsufficientAllowance` error if there are not enough tokens allowed
-        // /// for the caller to withdraw from `from`.
-        // ///
-        // /// Returns `InsufficientBalance` error if there are not enough tokens on
-        // /// the the account balance of `from`.
-        // #[ink(message, selector = "0xbb399017")]
-        // pub fn transfer_from(
-        //     &mut self,
-        //     _from: AccountId,
-        //     _to: AccountId,
-        //     _value: Balance,
-        // ) -> Result<()> {
-        //     unimplemented!()
-        // }
-        //
-        // /// Allows `spender` to withdraw from the caller's account multiple times, up to
-        // /// the `value` amount.
-        // ///
-        // /// If this function is called again it overwrites the current allowance with `value`.
-        // ///
-        // /// An `Approval` event is emitted.
-        // #[ink(message, selector = "0x4ce0e831")]
-        // pub fn approve(&mut self, _spender: AccountId, _value: Balance) -> Result<()> {
-        //     unimplemented!()
-        // }
         /// Returns the token name.
         #[ink(message, selector = "0xd3dc9f8c")]
         pub fn token_name(&self) -> Option<String> {
-            unimplemented!()
+            self.name.clone()
         }
 
         /// Returns the token symbol.
         #[ink(message, selector = "0xe4ab4943")]
         pub fn token_symbol(&self) -> Option<String> {
-            unimplemented!()
+            self.symbol.clone()
         }
 
         /// Returns the token decimals.
         #[ink(message, selector = "0xc4b508e2")]
         pub fn token_decimals(&self) -> Option<u8> {
-            unimplemented!()
+            self.decimals
         }
 
         /// Returns the total token supply.
         #[ink(message, selector = "0x557c8bd0")]
         pub fn total_supply(&self) -> Balance {
-            unimplemented!()
+            self.total_supply
         }
 
         /// Returns the account balance for the specified `owner`.
+        ///
+        /// Returns `0` if the account is non-existent.
         #[ink(message, selector = "0xb13e6c24")]
-        pub fn balance_of(&self, _owner: AccountId) -> Balance {
-            unimplemented!()
+        pub fn balance_of(&self, owner: AccountId) -> Balance {
+            self.balances.get(&owner).copied().unwrap_or(0)
         }
 
         /// Transfers `value` amount of tokens from the caller's account to account `to`.
+        ///
+        /// On success a `Transfer` event is emitted.
+        ///
+        /// # Errors
+        ///
+        /// Returns `InsufficientBalance` error if there are not enough tokens on
+        /// the caller's account balance.
         #[ink(message, selector = "0xa6b726c7")]
-        pub fn transfer(&mut self, _to: AccountId, _value: Balance) -> Result<()> {
-            unimplemented!()
+        pub fn transfer(&mut self, to: AccountId, value: Balance) -> Result<()> {
+            let from = self.env().caller();
+            self.transfer_from_to(from, to, value)
         }
 
         /// Returns the amount which `spender` is still allowed to withdraw from `owner`.
+        ///
+        /// Returns `0` if no allowance has been set `0`.
         #[ink(message, selector = "0x984f2ea5")]
-        pub fn allowance(&self, _owner: AccountId, _spender: AccountId) -> Balance {
-            unimplemented!()
+        pub fn allowance(&self, owner: AccountId, spender: AccountId) -> Balance {
+            self.allowances.get(&(owner, spender)).copied().unwrap_or(0)
         }
 
         /// Transfers `value` tokens on the behalf of `from` to the account `to`.
+        ///
+        /// On success a `Transfer` event is emitted.
+        ///
+        /// # Errors
+        ///
+        /// Returns `InsufficientAllowance` error if there are not enough tokens allowed
+        /// for the caller to withdraw from `from`.
+        ///
+        /// Returns `InsufficientBalance` error if there are not enough tokens on
+        /// the the account balance of `from`.
         #[ink(message, selector = "0xeb7943df")]
         pub fn transfer_from(
             &mut self,
-            _from: AccountId,
-            _to: AccountId,
-            _value: Balance,
+            from: AccountId,
+            to: AccountId,
+            value: Balance,
         ) -> Result<()> {
-            unimplemented!()
+            let caller = self.env().caller();
+            let allowance = self.allowance(from, caller);
+            if allowance < value {
+                return Err(Error::InsufficientAllowance);
+            }
+            self.transfer_from_to(from, to, value)?;
+            self.allowances.insert((from, caller), allowance - value);
+            Ok(())
         }
 
         /// Allows `spender` to withdraw from the caller's account multiple times, up to
         /// the `value` amount.
+        ///
+        /// If this function is called again it overwrites the current allowance with `value`.
+        ///
+        /// An `Approval` event is emitted.
         #[ink(message, selector = "0x702770d4")]
-        pub fn approve(&mut self, _spender: AccountId, _value: Balance) -> Result<()> {
-            unimplemented!()
+        pub fn approve(&mut self, spender: AccountId, value: Balance) -> Result<()> {
+            let owner = self.env().caller();
+            self.allowances.insert((owner, spender), value);
+            self.env().emit_event(Approval {
+                owner,
+                spender,
+                value,
+            });
+            Ok(())
         }
 
-        #[ink(message,payable)]
-        pub fn deposit(&mut self,) -> Result<()> {
-            // balanceOf[msg.sender] += msg.value;
-            // emit Deposit(msg.sender, msg.value);
-            unimplemented!()
+        /// Wraps the native balance sent with the call into as much cDOT.
+        ///
+        /// # Errors
+        ///
+        /// Returns `Overflow` error if the caller's balance or the total supply
+        /// would overflow.
+        #[ink(message, payable)]
+        pub fn deposit(&mut self) -> Result<()> {
+            let caller = self.env().caller();
+            let value = self.env().transferred_balance();
+            let balance = self.balance_of(caller).checked_add(value).ok_or(Error::Overflow)?;
+            let total_supply = self.total_supply.checked_add(value).ok_or(Error::Overflow)?;
+            self.balances.insert(caller, balance);
+            self.total_supply = total_supply;
+            self.env().emit_event(Deposit {
+                owner: caller,
+                value,
+            });
+            Ok(())
         }
 
+        /// Burns `amount` of the caller's cDOT and sends back as much native DOT.
+        ///
+        /// # Errors
+        ///
+        /// Returns `InsufficientBalance` error if the caller holds less than `amount`.
         #[ink(message)]
-        pub fn withdraw(&mut self,_amount:Balance) ->Result<()> {
-            unimplemented!()
-            // require(balanceOf[msg.sender] >= wad);
-            // balanceOf[msg.sender] -= wad;
-            // msg.sender.transfer(wad);
-            // emit Withdrawal(msg.sender, wad);
+        pub fn withdraw(&mut self, amount: Balance) -> Result<()> {
+            let caller = self.env().caller();
+            let balance = self.balance_of(caller);
+            if balance < amount {
+                return Err(Error::InsufficientBalance);
+            }
+            self.balances.insert(caller, balance - amount);
+            self.total_supply -= amount;
+            self.env().transfer(caller, amount).expect("ERR_DOT_FAILED");
+            self.env().emit_event(Withdrawal {
+                owner: caller,
+                value: amount,
+            });
+            Ok(())
         }
-        /// Issue a new amount of tokens
-        /// these tokens are deposited into the owner address
+
+        /// Kept for PAT compatibility; always fails, use `deposit`.
         #[ink(message, selector = "0xcfdd9aa2")]
         pub fn mint(&mut self, _user: AccountId, _amount: Balance) -> Result<()> {
-            unimplemented!()
+            Err(Error::Unsupported)
         }
 
-        /// Redeem tokens.
-        /// These tokens are withdrawn from the owner address
-        /// if the balance must be enough to cover the redeem
-        /// or the call will fail.
+        /// Kept for PAT compatibility; always fails, use `withdraw`.
         #[ink(message, selector = "0xb1efc17b")]
         pub fn burn(&mut self, _user: AccountId, _amount: Balance) -> Result<()> {
-            unimplemented!()
+            Err(Error::Unsupported)
+        }
+
+        fn transfer_from_to(
+            &mut self,
+            from: AccountId,
+            to: AccountId,
+            value: Balance,
+        ) -> Result<()> {
+            let from_balance = self.balance_of(from);
+            if from_balance < value {
+                return Err(Error::InsufficientBalance);
+            }
+            self.balances.insert(from, from_balance - value);
+            let to_balance = self.balance_of(to);
+            self.balances.insert(to, to_balance + value);
+            self.env().emit_event(Transfer {
+                from: Some(from),
+                to: Some(to),
+                value,
+            });
+            Ok(())
         }
     }
 }
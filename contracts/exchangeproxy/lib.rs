@@ -4,9 +4,10 @@ use ink_lang as ink;
 mod exchangeproxy {
     use cdot::PAT;
     use poolproxy::PoolInterface;
+    use factoryproxy::FactoryInterface;
     use ink_env::call::FromAccountId;
     use ink_env::debug_println;
-    use ink_lang::ToAccountId;
+    use ink_lang::{ForwardCallMut, ToAccountId};
     use ink_prelude::vec::Vec;
     use ink_storage::{
         // collections::{HashMap as StorageHashMap, Vec as StorageVec},
@@ -22,8 +23,15 @@ mod exchangeproxy {
         /// Stores a single `bool` value on the storage.
         _mutex: bool,
         cdot: Lazy<PAT>,
+        // only pools registered in one of these factories are swapped through
+        factories: Vec<AccountId>,
     }
 
+    /// Number of slices `view_split_*` divide the amount into.
+    const SPLIT_STEPS: u128 = 10;
+    /// Fixed-point one of the pools' ratios, `math::BONE`.
+    const BONE: u128 = 10000000000;
+
     #[derive(
     Debug, PartialEq, Eq, Clone, scale::Encode, scale::Decode, SpreadLayout, PackedLayout,
     )]
@@ -39,6 +47,25 @@ mod exchangeproxy {
         max_price: u128,
     }
 
+    /// One hop of a multihop swap sequence.
+    #[derive(
+    Debug, PartialEq, Eq, Clone, scale::Encode, scale::Decode, SpreadLayout, PackedLayout,
+    )]
+    #[cfg_attr(
+    feature = "std", derive(scale_info::TypeInfo, ink_storage::traits::StorageLayout)
+    )]
+    pub struct MultihopSwap {
+        pool: AccountId,
+        token_in: AccountId,
+        token_out: AccountId,
+        // exact in: amount in of the first hop, ignored for later hops (they swap the previous output)
+        // exact out: amount out of the last hop, ignored for earlier hops
+        swap_amount: u128,
+        // exact in: min amount out of this hop / exact out: max amount in of the first hop
+        limit_return_amount: u128,
+        max_price: u128,
+    }
+
     #[ink(event)]
     pub struct LOGCALL {
         // #[ink(topic)]
@@ -51,15 +78,237 @@ mod exchangeproxy {
     impl ExchangeProxy {
         /// Constructor that initializes the `bool` value to `false`.
         #[ink(constructor)]
-        pub fn new(cdot_contract: AccountId) -> Self {
+        pub fn new(cdot_contract: AccountId, factories: Vec<AccountId>) -> Self {
             assert_ne!(cdot_contract, Default::default());
+            assert!(factories.len() > 0, "ERR_NO_FACTORY");
             let cdot_token: PAT = FromAccountId::from_account_id(cdot_contract);
             Self {
                 _mutex: false,
                 cdot: Lazy::new(cdot_token),
+                factories,
+            }
+        }
+
+        #[ink(message)]
+        pub fn get_factories(&self) -> Vec<AccountId> {
+            self.factories.clone()
+        }
+
+        #[ink(message)]
+        pub fn is_registered_pool(&self, pool: AccountId) -> bool {
+            return self.factories.iter().any(|f| {
+                let factory: FactoryInterface = FromAccountId::from_account_id(*f);
+                factory.is_pool(pool)
+            });
+        }
+
+        fn _registered_pool(&self, pool: AccountId) -> PoolInterface {
+            assert!(self.is_registered_pool(pool), "ERR_NOT_POOL");
+            return FromAccountId::from_account_id(pool);
+        }
+
+        // Pools are approved for exactly what one swap may take, and the
+        // approval is revoked right after, so nothing is left for them to pull.
+        fn _swap_exact_amount_in(
+            &self,
+            pool_address: AccountId,
+            token_in: AccountId,
+            token_amount_in: u128,
+            token_out: AccountId,
+            min_amount_out: u128,
+            max_price: u128,
+            referrer: Option<AccountId>,
+        ) -> u128 {
+            // the pool only sees this proxy, so the trader is checked here
+            assert!(referrer != Some(self.env().caller()), "ERR_SELF_REFERRAL");
+            let pool = self._registered_pool(pool_address);
+            let mut ti: PAT = FromAccountId::from_account_id(token_in);
+            assert!(ti.approve(pool_address, token_amount_in).is_ok(), "ERR_APPROVE");
+            let (token_amount_out, _) = pool.swap_exact_amount_in(
+                token_in,
+                token_amount_in,
+                token_out,
+                min_amount_out,
+                max_price,
+                referrer,
+            );
+            assert!(ti.approve(pool_address, 0).is_ok(), "ERR_APPROVE");
+            token_amount_out
+        }
+
+        fn _swap_exact_amount_out(
+            &self,
+            pool_address: AccountId,
+            token_in: AccountId,
+            max_amount_in: u128,
+            token_out: AccountId,
+            token_amount_out: u128,
+            max_price: u128,
+            referrer: Option<AccountId>,
+        ) -> u128 {
+            // the pool only sees this proxy, so the trader is checked here
+            assert!(referrer != Some(self.env().caller()), "ERR_SELF_REFERRAL");
+            let pool = self._registered_pool(pool_address);
+            let mut ti: PAT = FromAccountId::from_account_id(token_in);
+            assert!(ti.approve(pool_address, max_amount_in).is_ok(), "ERR_APPROVE");
+            let (token_amount_in, _) = pool.swap_exact_amount_out(
+                token_in,
+                max_amount_in,
+                token_out,
+                token_amount_out,
+                max_price,
+                referrer,
+            );
+            assert!(ti.approve(pool_address, 0).is_ok(), "ERR_APPROVE");
+            token_amount_in
+        }
+
+        // Registered pools trading the pair, cheapest spot price first, at most `max_pools`.
+        fn _candidate_pools(&self, token_in: AccountId, token_out: AccountId, max_pools: u32) -> Vec<(PoolInterface, AccountId)> {
+            let mut candidates: Vec<(u128, AccountId)> = Vec::new();
+            for f in self.factories.iter() {
+                let factory: FactoryInterface = FromAccountId::from_account_id(*f);
+                for p in factory.pools_for_pair(token_in, token_out) {
+                    let pool: PoolInterface = FromAccountId::from_account_id(p);
+                    if pool.is_public_swap() && !candidates.iter().any(|c| c.1 == p) {
+                        candidates.push((pool.get_spot_price(token_in, token_out), p));
+                    }
+                }
             }
+            candidates.sort_by(|a, b| a.0.cmp(&b.0));
+            candidates.truncate(max_pools as usize);
+            assert!(candidates.len() > 0, "ERR_NO_POOL");
+            return candidates.into_iter()
+                .map(|(_, p)| (FromAccountId::from_account_id(p), p))
+                .collect();
+        }
+
+        /// Splits `amount` of `token_in` across up to `max_pools` registered pools
+        /// for the most `token_out`. Returns swaps for `batch_swap_exact_in` and the
+        /// expected total amount out.
+        #[ink(message)]
+        pub fn view_split_exact_in(
+            &self,
+            token_in: AccountId,
+            token_out: AccountId,
+            amount: u128,
+            max_pools: u32,
+        ) -> (Vec<Swap>, u128) {
+            let pools = self._candidate_pools(token_in, token_out, max_pools);
+            // stay within each pool's max_in_ratio, and within the cash it holds to pay out
+            let limits: Vec<u128> = pools.iter()
+                .map(|(pool, _)| self.mul(pool.get_balance(token_in), pool.get_config().max_in_ratio))
+                .collect();
+            let cash: Vec<u128> = pools.iter().map(|(pool, _)| pool.get_cash(token_out)).collect();
+            let mut amounts_in: Vec<u128> = ink_prelude::vec![0; pools.len()];
+            let mut amounts_out: Vec<u128> = ink_prelude::vec![0; pools.len()];
+
+            // give each slice to the pool adding the most output for it
+            let step = amount / SPLIT_STEPS;
+            for s in 0..SPLIT_STEPS {
+                let chunk = if s == SPLIT_STEPS - 1 { amount - step * (SPLIT_STEPS - 1) } else { step };
+                if chunk == 0 {
+                    continue;
+                }
+                let mut best: Option<(usize, u128)> = None;
+                for (i, (pool, _)) in pools.iter().enumerate() {
+                    let amount_in = self.add(amounts_in[i], chunk);
+                    if amount_in > limits[i] {
+                        continue;
+                    }
+                    let amount_out = pool.get_amount_out(token_in, amount_in, token_out);
+                    if amount_out > cash[i] {
+                        continue;
+                    }
+                    let gain = amount_out - amounts_out[i];
+                    if best.map_or(true, |(j, g)| gain > g || (gain == g && amounts_in[i] < amounts_in[j])) {
+                        best = Some((i, gain));
+                    }
+                }
+                let (i, gain) = best.expect("ERR_INSUFFICIENT_LIQUIDITY");
+                amounts_in[i] += chunk;
+                amounts_out[i] += gain;
+            }
+
+            let mut swaps: Vec<Swap> = Vec::new();
+            let mut total_amount_out: u128 = 0;
+            for (i, (_, p)) in pools.iter().enumerate() {
+                if amounts_in[i] == 0 {
+                    continue;
+                }
+                swaps.push(Swap {
+                    pool: *p,
+                    token_in_param: amounts_in[i],
+                    token_out_param: 0,
+                    max_price: u128::MAX,
+                });
+                total_amount_out = self.add(total_amount_out, amounts_out[i]);
+            }
+            (swaps, total_amount_out)
+        }
+
+        /// Splits buying `amount` of `token_out` across up to `max_pools` registered
+        /// pools for the least `token_in`. Returns swaps for `batch_swap_exact_out`
+        /// and the expected total amount in.
+        #[ink(message)]
+        pub fn view_split_exact_out(
+            &self,
+            token_in: AccountId,
+            token_out: AccountId,
+            amount: u128,
+            max_pools: u32,
+        ) -> (Vec<Swap>, u128) {
+            let pools = self._candidate_pools(token_in, token_out, max_pools);
+            // stay within each pool's max_out_ratio and the cash it holds
+            let limits: Vec<u128> = pools.iter()
+                .map(|(pool, _)| {
+                    let limit = self.mul(pool.get_balance(token_out), pool.get_config().max_out_ratio);
+                    core::cmp::min(limit, pool.get_cash(token_out))
+                })
+                .collect();
+            let mut amounts_in: Vec<u128> = ink_prelude::vec![0; pools.len()];
+            let mut amounts_out: Vec<u128> = ink_prelude::vec![0; pools.len()];
+
+            // give each slice to the pool charging the least extra input for it
+            let step = amount / SPLIT_STEPS;
+            for s in 0..SPLIT_STEPS {
+                let chunk = if s == SPLIT_STEPS - 1 { amount - step * (SPLIT_STEPS - 1) } else { step };
+                if chunk == 0 {
+                    continue;
+                }
+                let mut best: Option<(usize, u128)> = None;
+                for (i, (pool, _)) in pools.iter().enumerate() {
+                    let amount_out = self.add(amounts_out[i], chunk);
+                    if amount_out > limits[i] {
+                        continue;
+                    }
+                    let amount_in = pool.get_amount_in(token_in, token_out, amount_out);
+                    let cost = amount_in - amounts_in[i];
+                    if best.map_or(true, |(j, c)| cost < c || (cost == c && amounts_out[i] < amounts_out[j])) {
+                        best = Some((i, cost));
+                    }
+                }
+                let (i, cost) = best.expect("ERR_INSUFFICIENT_LIQUIDITY");
+                amounts_out[i] += chunk;
+                amounts_in[i] += cost;
+            }
+
+            let mut swaps: Vec<Swap> = Vec::new();
+            let mut total_amount_in: u128 = 0;
+            for (i, (_, p)) in pools.iter().enumerate() {
+                if amounts_out[i] == 0 {
+                    continue;
+                }
+                swaps.push(Swap {
+                    pool: *p,
+                    token_in_param: amounts_in[i],
+                    token_out_param: amounts_out[i],
+                    max_price: u128::MAX,
+                });
+                total_amount_in = self.add(total_amount_in, amounts_in[i]);
+            }
+            (swaps, total_amount_in)
         }
-        pub fn default() -> Self { Self::new(Default::default()) }
 
         #[ink(message)]
         pub fn batch_swap_exact_in(
@@ -69,6 +318,7 @@ mod exchangeproxy {
             token_out: AccountId,
             total_amount_in: u128,
             min_total_amount_out: u128,
+            referrer: Option<AccountId>,
         ) -> u128 {
             self._logs_();
             self._locks_();
@@ -92,19 +342,16 @@ mod exchangeproxy {
             ink_env::debug_println!("batch_swap_exact_in 1. =============");
 
             for x in swaps {
-                let pool: PoolInterface = FromAccountId::from_account_id(x.pool);
-                if ti_contract.allowance(self.env().account_id(), x.pool) < total_amount_in {
-                    ti_contract.approve(x.pool, u128::MAX);
-                }
-
                 ink_env::debug_println!("swap_exact_amount_in begin. =============");
 
-                let (token_amount_out,_) = pool.swap_exact_amount_in(
+                let token_amount_out = self._swap_exact_amount_in(
+                    x.pool,
                     token_in,
                     x.token_in_param,
                     token_out,
                     x.token_out_param,
                     x.max_price,
+                    referrer,
                 );
                 total_amount_out = self.add(token_amount_out, total_amount_out);
                 let message = ink_prelude::format!("token_amount_out is {:?}, total_amount_out is {:?}",
@@ -148,6 +395,7 @@ mod exchangeproxy {
             token_in: AccountId,
             token_out: AccountId,
             max_total_amount_in: u128,
+            referrer: Option<AccountId>,
         ) -> u128 {
             self._logs_();
             self._locks_();
@@ -164,18 +412,16 @@ mod exchangeproxy {
             assert!(ti_contract.transfer_from(caller, exchange_account, max_total_amount_in).is_ok());
             assert!(swaps.len() > 0, "swaps is empty");
             for x in swaps {
-                let pool: PoolInterface = FromAccountId::from_account_id(x.pool);
-                if ti_contract.allowance(self.env().account_id(), x.pool) < max_total_amount_in {
-                    ti_contract.approve(x.pool, u128::MAX);
-                }
                 ink_env::debug_println!("swap_exact_amount_out begin. =============");
 
-                let (token_amount_in,_) = pool.swap_exact_amount_out(
+                let token_amount_in = self._swap_exact_amount_out(
+                    x.pool,
                     token_in,
                     x.token_in_param,
                     token_out,
                     x.token_out_param,
                     x.max_price,
+                    referrer,
                 );
                 total_amount_in = self.add(token_amount_in, total_amount_in);
             }
@@ -209,45 +455,198 @@ mod exchangeproxy {
             total_amount_in
         }
 
+        // every sequence must lead from `token_in` to `token_out` one hop at a time
+        fn _check_sequences(&self, sequences: &Vec<Vec<MultihopSwap>>, token_in: AccountId, token_out: AccountId) {
+            assert!(sequences.len() > 0, "swaps is empty");
+            for sequence in sequences.iter() {
+                assert!(sequence.len() > 0, "swaps is empty");
+                assert!(sequence[0].token_in == token_in, "ERR_BAD_PATH");
+                assert!(sequence[sequence.len() - 1].token_out == token_out, "ERR_BAD_PATH");
+                for k in 1..sequence.len() {
+                    assert!(sequence[k].token_in == sequence[k - 1].token_out, "ERR_BAD_PATH");
+                }
+            }
+        }
+
+        // sends whatever this contract holds of `token` to `to`
+        fn _sweep(&self, token: AccountId, to: AccountId) {
+            let t: PAT = FromAccountId::from_account_id(token);
+            let balance = t.balance_of(self.env().account_id());
+            if balance > 0 {
+                self._trans_(token, to, balance);
+            }
+        }
+
+        /// Like `batch_swap_exact_in`, but each sequence is a path of hops through
+        /// possibly different tokens; intermediate tokens stay in the proxy.
         #[ink(message)]
+        pub fn multihop_batch_swap_exact_in(
+            &mut self,
+            sequences: Vec<Vec<MultihopSwap>>,
+            token_in: AccountId,
+            token_out: AccountId,
+            total_amount_in: u128,
+            min_total_amount_out: u128,
+            referrer: Option<AccountId>,
+        ) -> u128 {
+            self._logs_();
+            self._locks_();
+            self._check_sequences(&sequences, token_in, token_out);
+            let caller = self.env().caller();
+            let exchange_account = self.env().account_id();
+            let mut ti: PAT = FromAccountId::from_account_id(token_in);
+            assert!(ti.transfer_from(caller, exchange_account, total_amount_in).is_ok());
+
+            let mut total_amount_out: u128 = 0;
+            for sequence in sequences.iter() {
+                let mut token_amount_out: u128 = 0;
+                for (k, x) in sequence.iter().enumerate() {
+                    let swap_amount = if k == 0 { x.swap_amount } else { token_amount_out };
+                    token_amount_out = self._swap_exact_amount_in(
+                        x.pool,
+                        x.token_in,
+                        swap_amount,
+                        x.token_out,
+                        x.limit_return_amount,
+                        x.max_price,
+                        referrer,
+                    );
+                }
+                total_amount_out = self.add(token_amount_out, total_amount_out);
+            }
+            assert!(total_amount_out >= min_total_amount_out, "ERR_LIMIT_OUT");
+
+            self._sweep(token_out, caller);
+            self._sweep(token_in, caller);
+            self._unlocks_();
+            total_amount_out
+        }
+
+        /// Like `batch_swap_exact_out`, but each sequence is a path of hops; the
+        /// amount each hop must produce is quoted backwards from the last one.
+        /// A sequence may not pass through the same pool twice.
+        #[ink(message)]
+        pub fn multihop_batch_swap_exact_out(
+            &mut self,
+            sequences: Vec<Vec<MultihopSwap>>,
+            token_in: AccountId,
+            token_out: AccountId,
+            max_total_amount_in: u128,
+            referrer: Option<AccountId>,
+        ) -> u128 {
+            self._logs_();
+            self._locks_();
+            self._check_sequences(&sequences, token_in, token_out);
+            let caller = self.env().caller();
+            let exchange_account = self.env().account_id();
+            let mut ti: PAT = FromAccountId::from_account_id(token_in);
+            assert!(ti.transfer_from(caller, exchange_account, max_total_amount_in).is_ok());
+
+            let mut total_amount_in: u128 = 0;
+            let mut intermediates: Vec<AccountId> = Vec::new();
+            for sequence in sequences.iter() {
+                // hops are quoted before any of them runs, which only holds if no
+                // hop trades in a pool an earlier hop of the sequence already moved
+                for k in 1..sequence.len() {
+                    assert!(sequence[..k].iter().all(|x| x.pool != sequence[k].pool), "ERR_POOL_REUSED");
+                }
+                let last = sequence.len() - 1;
+                // amounts[k] is what hop k has to produce
+                let mut amounts: Vec<u128> = ink_prelude::vec![0; sequence.len()];
+                amounts[last] = sequence[last].swap_amount;
+                for k in (1..sequence.len()).rev() {
+                    let pool: PoolInterface = self._registered_pool(sequence[k].pool);
+                    amounts[k - 1] = pool.get_amount_in(sequence[k].token_in, sequence[k].token_out, amounts[k]);
+                }
+
+                for (k, x) in sequence.iter().enumerate() {
+                    let max_amount_in = if k == 0 { x.limit_return_amount } else { amounts[k - 1] };
+                    let token_amount_in = self._swap_exact_amount_out(
+                        x.pool,
+                        x.token_in,
+                        max_amount_in,
+                        x.token_out,
+                        amounts[k],
+                        x.max_price,
+                        referrer,
+                    );
+                    if k == 0 {
+                        total_amount_in = self.add(token_amount_in, total_amount_in);
+                    } else if !intermediates.contains(&x.token_in) {
+                        intermediates.push(x.token_in);
+                    }
+                }
+            }
+            assert!(total_amount_in <= max_total_amount_in, "ERR_LIMIT_IN");
+
+            // rounding may leave dust of an intermediate token
+            for t in intermediates {
+                self._sweep(t, caller);
+            }
+            self._sweep(token_out, caller);
+            self._sweep(token_in, caller);
+            self._unlocks_();
+            total_amount_in
+        }
+
+        // Wraps `value` of the native balance sent with this call into cDOT.
+        fn _wrap_dot(&mut self, value: Balance) {
+            let mut cdot: PAT = FromAccountId::from_account_id(self.cdot.to_account_id());
+            let r = cdot.call_mut()
+                .deposit()
+                .transferred_value(value)
+                .fire()
+                .expect("ERR_DEPOSIT_FAILED");
+            assert!(r.is_ok(), "ERR_DEPOSIT_FAILED");
+        }
+
+        // Unwraps all cDOT this contract holds and sends it to `to` as native balance.
+        fn _unwrap_dot_to(&mut self, to: AccountId) {
+            let cdot_balance = self.cdot.balance_of(self.env().account_id());
+            if cdot_balance > 0 {
+                assert!(self.cdot.withdraw(cdot_balance).is_ok(), "ERR_WITHDRAW_FAILED");
+                self.env().transfer(to, cdot_balance).expect("ERR_DOT_FAILED");
+            }
+        }
+
+        /// Sells the native DOT sent with the call; whatever the swaps leave
+        /// unspent is refunded in DOT.
+        #[ink(message, payable)]
         pub fn batch_dot_in_swap_exact_in(
             &mut self,
             swaps: Vec<Swap>,
             token_out: AccountId,
             min_total_amount_out: u128,
+            referrer: Option<AccountId>,
         ) -> u128 {
             self._logs_();
             self._locks_();
+            let caller = self.env().caller();
+            let total_amount_in = self.env().transferred_balance();
             let mut total_amount_out: u128 = 0;
-            let mut to: PAT = FromAccountId::from_account_id(token_out);
-            self.cdot.deposit();
             assert!(swaps.len() > 0, "swaps is empty");
+            self._wrap_dot(total_amount_in);
+            let cdot_address = self.cdot.to_account_id();
             for x in swaps {
-                let pool: PoolInterface = FromAccountId::from_account_id(x.pool);
-                if self.cdot.allowance(self.env().account_id(), x.pool) < self.env().balance() {
-                    self.cdot.approve(x.pool, u128::MAX);
-                }
-                let (token_amount_out,_) = pool.swap_exact_amount_in(
-                    self.cdot.to_account_id(),
+                let token_amount_out = self._swap_exact_amount_in(
+                    x.pool,
+                    cdot_address,
                     x.token_in_param,
                     token_out,
                     x.token_out_param,
                     x.max_price,
+                    referrer,
                 );
                 total_amount_out = self.add(token_amount_out, total_amount_out);
             }
-            assert!(total_amount_out >= min_total_amount_out);
-            assert!(to.transfer(self.env().caller(), to.balance_of(self.env().account_id())).is_ok());
-            let cdot_balance = self.cdot.balance_of(self.env().account_id());
-            if cdot_balance > 0 {
-                self.cdot.withdraw(cdot_balance);
-                // (bool xfer,) = msg.sender.call.value(cdot_balance)("");
-                // require(xfer, "ERR_ETH_FAILED");
-            }
+            assert!(total_amount_out >= min_total_amount_out, "ERR_LIMIT_OUT");
+            self._sweep(token_out, caller);
+            self._unwrap_dot_to(caller);
             self._unlocks_();
             total_amount_out
         }
 
+        /// Sells `token_in` for cDOT and pays the proceeds out in native DOT.
         #[ink(message)]
         pub fn batch_dot_out_swap_exact_in(
             &mut self,
@@ -255,109 +654,104 @@ mod exchangeproxy {
             token_in: AccountId,
             total_amount_in: u128,
             min_total_amount_out: u128,
+            referrer: Option<AccountId>,
         ) -> u128 {
             self._logs_();
             self._locks_();
+            let caller = self.env().caller();
             let mut total_amount_out: u128 = 0;
             let mut ti: PAT = FromAccountId::from_account_id(token_in);
-            assert!(ti.transfer_from(self.env().caller(), self.env().account_id(), total_amount_in).is_ok());
+            assert!(ti.transfer_from(caller, self.env().account_id(), total_amount_in).is_ok());
             assert!(swaps.len() > 0, "swaps is empty");
+            let cdot_address = self.cdot.to_account_id();
             for x in swaps {
-                let pool: PoolInterface = FromAccountId::from_account_id(x.pool);
-                if ti.allowance(self.env().account_id(), x.pool) < total_amount_in {
-                    ti.approve(x.pool, u128::MAX);
-                }
-                let (token_amount_out,_) = pool.swap_exact_amount_in(
+                let token_amount_out = self._swap_exact_amount_in(
+                    x.pool,
                     token_in,
                     x.token_in_param,
-                    self.cdot.to_account_id(),
+                    cdot_address,
                     x.token_out_param,
                     x.max_price,
+                    referrer,
                 );
                 total_amount_out = self.add(token_amount_out, total_amount_out);
             }
-            assert!(total_amount_out >= min_total_amount_out);
-            let cdot_balance = self.cdot.balance_of(self.env().account_id());
-            self.cdot.withdraw(cdot_balance);
-            // (bool xfer,) = msg.sender.call.value(cdot_balance)("");
-            // require(xfer, "ERR_ETH_FAILED");
-            assert!(ti.transfer(self.env().caller(), ti.balance_of(self.env().account_id())).is_ok());
+            assert!(total_amount_out >= min_total_amount_out, "ERR_LIMIT_OUT");
+            self._unwrap_dot_to(caller);
+            self._sweep(token_in, caller);
             self._unlocks_();
             total_amount_out
         }
-        #[ink(message)]
+
+        /// Buys exact amounts of `token_out` with the native DOT sent with the
+        /// call, which is the most that may be spent; the rest is refunded in DOT.
+        #[ink(message, payable)]
         pub fn batch_dot_in_swap_exact_out(
             &mut self,
             swaps: Vec<Swap>,
             token_out: AccountId,
+            referrer: Option<AccountId>,
         ) -> u128 {
             self._logs_();
             self._locks_();
+            let caller = self.env().caller();
+            let max_total_amount_in = self.env().transferred_balance();
             let mut total_amount_in: u128 = 0;
-            let mut to: PAT = FromAccountId::from_account_id(token_out);
-            self.cdot.deposit();
             assert!(swaps.len() > 0, "swaps is empty");
+            self._wrap_dot(max_total_amount_in);
+            let cdot_address = self.cdot.to_account_id();
             for x in swaps {
-                let pool: PoolInterface = FromAccountId::from_account_id(x.pool);
-                if to.allowance(self.env().account_id(), x.pool) < self.env().balance() {
-                    to.approve(x.pool, u128::MAX);
-                }
-                let (token_amount_in,_) = pool.swap_exact_amount_out(
-                    self.cdot.to_account_id(),
+                let token_amount_in = self._swap_exact_amount_out(
+                    x.pool,
+                    cdot_address,
                     x.token_in_param,
                     token_out,
                     x.token_out_param,
                     x.max_price,
+                    referrer,
                 );
                 total_amount_in = self.add(token_amount_in, total_amount_in);
-                assert!(to.transfer(self.env().caller(), to.balance_of(self.env().account_id())).is_ok());
-                let cdot_balance = self.cdot.balance_of(self.env().account_id());
-                if cdot_balance > 0 {
-                    self.cdot.withdraw(cdot_balance);
-                    // (bool xfer,) = msg.sender.call.value(cdot_balance)("");
-                    // assert_eq!(xfer,false);
-                }
             }
+            assert!(total_amount_in <= max_total_amount_in, "ERR_LIMIT_IN");
+            self._sweep(token_out, caller);
+            self._unwrap_dot_to(caller);
             self._unlocks_();
             total_amount_in
         }
 
+        /// Buys exact amounts of native DOT, spending at most `max_total_amount_in`
+        /// of `token_in`; the unspent rest is refunded.
         #[ink(message)]
         pub fn batch_dot_out_swap_exact_out(
             &mut self,
             swaps: Vec<Swap>,
             token_in: AccountId,
             max_total_amount_in: u128,
+            referrer: Option<AccountId>,
         ) -> u128 {
-
             self._logs_();
             self._locks_();
+            let caller = self.env().caller();
             let mut total_amount_in: u128 = 0;
             let mut ti: PAT = FromAccountId::from_account_id(token_in);
-            assert!(ti.transfer_from(self.env().caller(), self.env().account_id(), max_total_amount_in).is_ok());
-            // let swap: Vec<_> = swaps.iter().copied().collect();
-            // for x in swap.clone().into_iter() {
+            assert!(ti.transfer_from(caller, self.env().account_id(), max_total_amount_in).is_ok());
             assert!(swaps.len() > 0, "swaps is empty");
+            let cdot_address = self.cdot.to_account_id();
             for x in swaps {
-                let pool: PoolInterface = FromAccountId::from_account_id(x.pool);
-                if ti.allowance(self.env().account_id(), x.pool) < max_total_amount_in {
-                    ti.approve(x.pool, u128::MAX);
-                }
-                let (token_amount_in,_) = pool.swap_exact_amount_out(
+                let token_amount_in = self._swap_exact_amount_out(
+                    x.pool,
                     token_in,
                     x.token_in_param,
-                    self.cdot.to_account_id(),
+                    cdot_address,
                     x.token_out_param,
                     x.max_price,
+                    referrer,
                 );
                 total_amount_in = self.add(token_amount_in, total_amount_in);
             }
-            assert!(max_total_amount_in <= max_total_amount_in);
-            assert!(ti.transfer(self.env().caller(), ti.balance_of(self.env().account_id())).is_ok());
-            let cdot_balance = self.cdot.balance_of(self.env().account_id());
-            self.cdot.withdraw(cdot_balance);
-            // (bool xfer,) = msg.sender.call.value(cdot_balance)("");
-            // assert!(xfer);
+            assert!(total_amount_in <= max_total_amount_in, "ERR_LIMIT_IN");
+            self._sweep(token_in, caller);
+            self._unwrap_dot_to(caller);
             self._unlocks_();
             total_amount_in
         }
@@ -390,6 +784,12 @@ mod exchangeproxy {
             c
         }
 
+        // fixed-point multiply, rounding like `math::bmul`
+        fn mul(&self, a: u128, b: u128) -> u128 {
+            let c = a.checked_mul(b).expect("mul is overflow ");
+            self.add(c, BONE / 2) / BONE
+        }
+
         fn _logs_(&mut self) {
             // emit LOG_CALL(msg.sig, msg.sender, msg.data);
             let sender = self.env().caller();
@@ -1,9 +1,23 @@
 #![cfg_attr(not(feature = "std"), no_std)]
-pub use self::pool::PoolInterface;
+pub use self::pool::{PoolInterface, PoolConfig};
 use ink_lang as ink;
 
 #[ink::contract]
 mod pool {
+    /// Mirrors `pool::PoolConfig`; fields must stay in the same order to decode.
+    #[derive(Debug, PartialEq, Eq, Clone, scale::Encode, scale::Decode)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub struct PoolConfig {
+        pub min_bound_tokens: u128,
+        pub max_bound_tokens: u128,
+        pub min_weight: u128,
+        pub max_weight: u128,
+        pub max_total_weight: u128,
+        pub min_balance: u128,
+        pub max_in_ratio: u128,
+        pub max_out_ratio: u128,
+    }
+
     /// Defines the storage of your contract.
     /// Add new fields to the below struct in order
     /// to add new static storage fields to your contract.
@@ -27,6 +41,7 @@ mod pool {
                                     token_out: AccountId,
                                     min_amount_out: u128,
                                     max_price: u128,
+                                    referrer: Option<AccountId>,
         ) -> (u128,u128) {unimplemented!()}
 
         #[ink(message)]
@@ -36,7 +51,37 @@ mod pool {
                                      token_out: AccountId,
                                      token_amount_out: u128,
                                      max_price: u128,
+                                     referrer: Option<AccountId>,
         ) -> (u128,u128) {unimplemented!()}
+
+        #[ink(message)]
+        pub fn get_amount_out(&self,
+                              token_in: AccountId,
+                              token_amount_in: u128,
+                              token_out: AccountId,
+        ) -> u128 {unimplemented!()}
+
+        #[ink(message)]
+        pub fn get_amount_in(&self,
+                             token_in: AccountId,
+                             token_out: AccountId,
+                             token_amount_out: u128,
+        ) -> u128 {unimplemented!()}
+
+        #[ink(message)]
+        pub fn is_public_swap(&self) -> bool {unimplemented!()}
+
+        #[ink(message)]
+        pub fn get_balance(&self, token: AccountId) -> u128 {unimplemented!()}
+
+        #[ink(message)]
+        pub fn get_spot_price(&self, token_in: AccountId, token_out: AccountId) -> u128 {unimplemented!()}
+
+        #[ink(message)]
+        pub fn get_config(&self) -> PoolConfig {unimplemented!()}
+
+        #[ink(message)]
+        pub fn get_cash(&self, token: AccountId) -> u128 {unimplemented!()}
         /// Constructor that initializes the `bool` value to `false`.
         ///
         /// Constructors can delegate to other constructors.
@@ -5,5 +5,8 @@ set -eu
 cargo +nightly contract build --manifest-path math/Cargo.toml
 cargo +nightly contract build --manifest-path base/Cargo.toml
 cargo +nightly contract build --manifest-path token/Cargo.toml
+cargo +nightly contract build --manifest-path factoryproxy/Cargo.toml
 cargo +nightly contract build --manifest-path pool/Cargo.toml
 cargo +nightly contract build
+cargo +nightly contract build --manifest-path farm/Cargo.toml
+cargo +nightly contract build --manifest-path crp/Cargo.toml
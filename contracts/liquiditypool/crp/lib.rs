@@ -0,0 +1,586 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+pub use self::crp::{ConfigurableRightsPool, Rights, NewToken};
+use ink_lang as ink;
+
+#[ink::contract]
+mod crp {
+    use ink_prelude::vec::Vec;
+    use ink_storage::{
+        collections::HashMap as StorageHashMap,
+        traits::{PackedLayout, SpreadLayout},
+        Lazy,
+    };
+    use ink_env::call::FromAccountId;
+    use ink_lang::ToAccountId;
+
+    use math::Math;
+    use math::EXIT_FEE;
+    use token::Token;
+    use pool::Pool;
+    use cdot::PAT;
+
+    /// The changes the controller is allowed to make to the underlying pool,
+    /// declared once at construction.
+    #[derive(
+    Debug, PartialEq, Eq, Clone, Default, scale::Encode, scale::Decode, SpreadLayout, PackedLayout,
+    )]
+    #[cfg_attr(
+    feature = "std",
+    derive(scale_info::TypeInfo, ink_storage::traits::StorageLayout)
+    )]
+    pub struct Rights {
+        pub can_change_swap_fee: bool,
+        pub can_change_weights: bool,
+        pub can_add_remove_tokens: bool,
+        pub can_whitelist_lps: bool,
+        pub can_change_cap: bool,
+    }
+
+    /// A token committed for addition, bindable once the timelock has passed.
+    #[derive(
+    Debug, PartialEq, Eq, Clone, scale::Encode, scale::Decode, SpreadLayout, PackedLayout,
+    )]
+    #[cfg_attr(
+    feature = "std",
+    derive(scale_info::TypeInfo, ink_storage::traits::StorageLayout)
+    )]
+    pub struct NewToken {
+        pub token: AccountId,
+        pub balance: u128,
+        pub denorm: u128,
+        pub commit_block: BlockNumber,
+    }
+
+    #[ink(storage)]
+    pub struct ConfigurableRightsPool {
+        mutex: bool,
+        controller: AccountId,
+        pending_controller: Option<AccountId>,
+        rights: Rights,
+        created: bool,
+        cap: u128,
+        add_token_time_lock: BlockNumber,
+        new_token: Option<NewToken>,
+        removal: Option<(AccountId, BlockNumber)>,   // token committed for removal, and when
+        whitelist: StorageHashMap<AccountId, bool>,
+
+        math: Lazy<Math>,
+        pool: Lazy<Pool>,
+        token: Lazy<Token>,
+    }
+
+    #[ink(event)]
+    pub struct LogJoin {
+        #[ink(topic)]
+        caller: Option<AccountId>,
+        #[ink(topic)]
+        token_in: Option<AccountId>,
+        token_amount_in: u128,
+    }
+
+    #[ink(event)]
+    pub struct LogExit {
+        #[ink(topic)]
+        caller: Option<AccountId>,
+        #[ink(topic)]
+        token_out: Option<AccountId>,
+        token_amount_out: u128,
+    }
+
+    #[ink(event)]
+    pub struct LogUpdateWeight {
+        #[ink(topic)]
+        token: Option<AccountId>,
+        old_weight: u128,
+        new_weight: u128,
+    }
+
+    #[ink(event)]
+    pub struct LogCommitToken {
+        #[ink(topic)]
+        token: Option<AccountId>,
+        balance: u128,
+        denorm: u128,
+        commit_block: BlockNumber,
+    }
+
+    #[ink(event)]
+    pub struct LogAddToken {
+        #[ink(topic)]
+        token: Option<AccountId>,
+        balance: u128,
+        denorm: u128,
+    }
+
+    #[ink(event)]
+    pub struct LogCommitRemoveToken {
+        #[ink(topic)]
+        token: Option<AccountId>,
+        commit_block: BlockNumber,
+    }
+
+    #[ink(event)]
+    pub struct LogRemoveToken {
+        #[ink(topic)]
+        token: Option<AccountId>,
+    }
+
+    #[ink(event)]
+    pub struct LogWhitelist {
+        #[ink(topic)]
+        account: Option<AccountId>,
+        whitelisted: bool,
+    }
+
+    #[ink(event)]
+    pub struct LogCap {
+        cap: u128,
+    }
+
+    impl ConfigurableRightsPool {
+        /// `pool` and `token` must have this contract set as their controller before
+        /// `create_pool`; `token` is the LP token this contract issues.
+        #[ink(constructor)]
+        pub fn new(math_address: AccountId,
+                   pool_address: AccountId,
+                   token_address: AccountId,
+                   rights: Rights,
+                   add_token_time_lock: BlockNumber) -> Self {
+            let math: Math = FromAccountId::from_account_id(math_address);
+            let pool: Pool = FromAccountId::from_account_id(pool_address);
+            let token: Token = FromAccountId::from_account_id(token_address);
+            Self {
+                mutex: false,
+                controller: Self::env().caller(),
+                pending_controller: None,
+                rights,
+                created: false,
+                cap: u128::MAX,
+                add_token_time_lock,
+                new_token: None,
+                removal: None,
+                whitelist: StorageHashMap::new(),
+
+                math: Lazy::new(math),
+                pool: Lazy::new(pool),
+                token: Lazy::new(token),
+            }
+        }
+
+        fn _lock_(&mut self) {
+            assert!(!self.mutex, "ERR_REENTRY");
+            self.mutex = true;
+        }
+
+        fn _unlock_(&mut self) {
+            self.mutex = false;
+        }
+
+        fn _only_controller(&self) {
+            assert!(self.env().caller() == self.controller, "ERR_NOT_CONTROLLER");
+        }
+
+        fn _require_created(&self) {
+            assert!(self.created, "ERR_NOT_CREATED");
+        }
+
+        fn _pull_underlying(&self, erc20: AccountId, from: AccountId, amount: u128) {
+            let mut erc: PAT = FromAccountId::from_account_id(erc20);
+            let r = erc.transfer_from(from, self.env().account_id(), amount).is_ok();
+            assert!(r, "ERR_ERC20_FALSE");
+        }
+
+        fn _push_underlying(&self, erc20: AccountId, to: AccountId, amount: u128) {
+            let mut erc: PAT = FromAccountId::from_account_id(erc20);
+            let r = erc.transfer(to, amount).is_ok();
+            assert!(r, "ERR_ERC20_FALSE");
+        }
+
+        // what the underlying pool actually pays out when `amount` is withdrawn by rebind
+        fn _after_exit_fee(&self, amount: u128) -> u128 {
+            return self.math.bsub(amount, self.math.bmul(amount, EXIT_FEE));
+        }
+
+        fn _mint_pool_share_to(&mut self, to: AccountId, amount: u128) {
+            self.token.mint(amount);
+            self.token.push(to, amount);
+        }
+
+        fn _burn_pool_share_from(&mut self, from: AccountId, amount: u128) {
+            self.token.pull(from, amount);
+            self.token.burn(amount);
+        }
+
+        fn _bind(&mut self, token: AccountId, balance: u128, denorm: u128) {
+            let pool_address = self.pool.to_account_id();
+            let mut erc: PAT = FromAccountId::from_account_id(token);
+            assert!(erc.approve(pool_address, u128::MAX).is_ok(), "ERR_ERC20_FALSE");
+            self.pool.bind(token, balance, denorm);
+        }
+
+        #[ink(message)]
+        pub fn get_controller(&self) -> AccountId {
+            self.controller
+        }
+
+        #[ink(message)]
+        pub fn get_pending_controller(&self) -> Option<AccountId> {
+            self.pending_controller
+        }
+
+        /// First step of handing control over; `controller` must call `accept_controller`.
+        #[ink(message)]
+        pub fn propose_controller(&mut self, controller: AccountId) {
+            self._only_controller();
+            self.pending_controller = Some(controller);
+        }
+
+        #[ink(message)]
+        pub fn accept_controller(&mut self) {
+            let sender = self.env().caller();
+            assert!(self.pending_controller == Some(sender), "ERR_NOT_PENDING_CONTROLLER");
+            self.controller = sender;
+            self.pending_controller = None;
+        }
+
+        #[ink(message)]
+        pub fn get_rights(&self) -> Rights {
+            self.rights.clone()
+        }
+
+        #[ink(message)]
+        pub fn get_pool(&self) -> AccountId {
+            self.pool.to_account_id()
+        }
+
+        #[ink(message)]
+        pub fn get_token(&self) -> AccountId {
+            self.token.to_account_id()
+        }
+
+        #[ink(message)]
+        pub fn get_cap(&self) -> u128 {
+            self.cap
+        }
+
+        #[ink(message)]
+        pub fn get_add_token_time_lock(&self) -> BlockNumber {
+            self.add_token_time_lock
+        }
+
+        #[ink(message)]
+        pub fn get_new_token(&self) -> Option<NewToken> {
+            self.new_token.clone()
+        }
+
+        #[ink(message)]
+        pub fn get_removal(&self) -> Option<(AccountId, BlockNumber)> {
+            self.removal
+        }
+
+        #[ink(message)]
+        pub fn is_whitelisted(&self, account: AccountId) -> bool {
+            self.whitelist.get(&account).copied().unwrap_or(false)
+        }
+
+        /// Anyone may join unless LP whitelisting is one of the pool's rights.
+        #[ink(message)]
+        pub fn can_provide_liquidity(&self, account: AccountId) -> bool {
+            return !self.rights.can_whitelist_lps || self.is_whitelisted(account);
+        }
+
+        /// Binds the initial tokens, pulled from the controller, and mints
+        /// `initial_supply` LP shares to it. Swapping is public from then on.
+        #[ink(message)]
+        pub fn create_pool(&mut self,
+                           initial_supply: u128,
+                           tokens: Vec<AccountId>,
+                           balances: Vec<u128>,
+                           weights: Vec<u128>,
+                           swap_fee: u128) {
+            self._only_controller();
+            self._lock_();
+            assert!(!self.created, "ERR_IS_CREATED");
+            assert!(initial_supply != 0 && initial_supply <= self.cap, "ERR_INIT_SUPPLY");
+            assert!(tokens.len() == balances.len() && tokens.len() == weights.len(), "ERR_LENGTH_MISMATCH");
+
+            let this = self.env().account_id();
+            assert!(self.pool.get_controller() == this, "ERR_NOT_POOL_CONTROLLER");
+            assert!(self.token.get_controller() == this, "ERR_NOT_TOKEN_CONTROLLER");
+            assert!(self.pool.get_num_tokens() == 0, "ERR_POOL_NOT_EMPTY");
+
+            let sender = self.env().caller();
+            for i in 0..tokens.len() {
+                self._pull_underlying(tokens[i], sender, balances[i]);
+                self._bind(tokens[i], balances[i], weights[i]);
+            }
+            self.pool.set_swap_fee(swap_fee);
+            self.pool.set_public_swap(true);
+
+            self.created = true;
+            self._mint_pool_share_to(sender, initial_supply);
+            self._unlock_();
+        }
+
+        #[ink(message)]
+        pub fn join_pool(&mut self, pool_amount_out: u128, max_amounts_in: Vec<u128>) {
+            self._lock_();
+            self._require_created();
+            let sender = self.env().caller();
+            assert!(self.can_provide_liquidity(sender), "ERR_NOT_ON_WHITELIST");
+
+            let pool_total = self.token.total_supply();
+            assert!(self.math.badd(pool_total, pool_amount_out) <= self.cap, "ERR_CAP_LIMIT_REACHED");
+            let ratio = self.math.bdiv(pool_amount_out, pool_total);
+            assert!(ratio != 0, "ERR_MATH_APPROX");
+
+            let tokens = self.pool.get_current_tokens();
+            assert!(max_amounts_in.len() == tokens.len(), "ERR_LENGTH_MISMATCH");
+            for (i, t) in tokens.iter().enumerate() {
+                let bal = self.pool.get_balance(*t);
+                let token_amount_in = self.math.bmul(ratio, bal);
+                assert!(token_amount_in != 0, "ERR_MATH_APPROX");
+                assert!(token_amount_in <= max_amounts_in[i], "ERR_LIMIT_IN");
+
+                self._pull_underlying(*t, sender, token_amount_in);
+                let denorm = self.pool.get_denormalized_weight(*t);
+                self.pool.rebind(*t, self.math.badd(bal, token_amount_in), denorm);
+                self.env().emit_event(LogJoin {
+                    caller: Some(sender),
+                    token_in: Some(*t),
+                    token_amount_in,
+                });
+            }
+
+            self._mint_pool_share_to(sender, pool_amount_out);
+            self._unlock_();
+        }
+
+        #[ink(message)]
+        pub fn exit_pool(&mut self, pool_amount_in: u128, min_amounts_out: Vec<u128>) {
+            self._lock_();
+            self._require_created();
+            let sender = self.env().caller();
+
+            let pool_total = self.token.total_supply();
+            let ratio = self.math.bdiv(pool_amount_in, pool_total);
+            assert!(ratio != 0, "ERR_MATH_APPROX");
+
+            self._burn_pool_share_from(sender, pool_amount_in);
+
+            let tokens = self.pool.get_current_tokens();
+            assert!(min_amounts_out.len() == tokens.len(), "ERR_LENGTH_MISMATCH");
+            for (i, t) in tokens.iter().enumerate() {
+                let bal = self.pool.get_balance(*t);
+                let token_amount_out = self.math.bmul(ratio, bal);
+                assert!(token_amount_out != 0, "ERR_MATH_APPROX");
+
+                let denorm = self.pool.get_denormalized_weight(*t);
+                self.pool.rebind(*t, self.math.bsub(bal, token_amount_out), denorm);
+                let token_amount_out = self._after_exit_fee(token_amount_out);
+                assert!(token_amount_out >= min_amounts_out[i], "ERR_LIMIT_OUT");
+
+                self._push_underlying(*t, sender, token_amount_out);
+                self.env().emit_event(LogExit {
+                    caller: Some(sender),
+                    token_out: Some(*t),
+                    token_amount_out,
+                });
+            }
+            self._unlock_();
+        }
+
+        #[ink(message)]
+        pub fn set_swap_fee(&mut self, swap_fee: u128) {
+            self._only_controller();
+            self._require_created();
+            assert!(self.rights.can_change_swap_fee, "ERR_NOT_CONFIGURABLE_SWAP_FEE");
+            self.pool.set_swap_fee(swap_fee);
+        }
+
+        /// Changes a token's weight while keeping its price: the controller adds or
+        /// receives the matching token balance and LP shares are minted or burnt in
+        /// proportion to the weight change.
+        #[ink(message)]
+        pub fn update_weight(&mut self, token: AccountId, new_weight: u128) {
+            self._lock_();
+            self._only_controller();
+            self._require_created();
+            assert!(self.rights.can_change_weights, "ERR_NOT_CONFIGURABLE_WEIGHTS");
+            assert!(self.pool.is_bound(token), "ERR_NOT_BOUND");
+
+            let sender = self.env().caller();
+            let old_weight = self.pool.get_denormalized_weight(token);
+            let total_weight = self.pool.get_total_denormalized_weight();
+            let balance = self.pool.get_balance(token);
+            let total_supply = self.token.total_supply();
+
+            if new_weight < old_weight {
+                let delta_weight = self.math.bsub(old_weight, new_weight);
+                let shares = self.math.bmul(total_supply, self.math.bdiv(delta_weight, total_weight));
+                let delta_balance = self.math.bmul(balance, self.math.bdiv(delta_weight, old_weight));
+
+                self._burn_pool_share_from(sender, shares);
+                self.pool.rebind(token, self.math.bsub(balance, delta_balance), new_weight);
+                let amount = self._after_exit_fee(delta_balance);
+                self._push_underlying(token, sender, amount);
+            } else if new_weight > old_weight {
+                let delta_weight = self.math.bsub(new_weight, old_weight);
+                let shares = self.math.bmul(total_supply, self.math.bdiv(delta_weight, total_weight));
+                let delta_balance = self.math.bmul(balance, self.math.bdiv(delta_weight, old_weight));
+
+                self._pull_underlying(token, sender, delta_balance);
+                self.pool.rebind(token, self.math.badd(balance, delta_balance), new_weight);
+                self._mint_pool_share_to(sender, shares);
+            }
+
+            self.env().emit_event(LogUpdateWeight {
+                token: Some(token),
+                old_weight,
+                new_weight,
+            });
+            self._unlock_();
+        }
+
+        /// First step of adding a token; `apply_add_token` binds it once
+        /// `add_token_time_lock` blocks have passed.
+        #[ink(message)]
+        pub fn commit_add_token(&mut self, token: AccountId, balance: u128, denorm: u128) {
+            self._only_controller();
+            self._require_created();
+            assert!(self.rights.can_add_remove_tokens, "ERR_CANNOT_ADD_REMOVE_TOKENS");
+            assert!(!self.pool.is_bound(token), "ERR_IS_BOUND");
+            // checked again by the pool's bind, but failing there only after the timelock
+            let config = self.pool.get_config();
+            assert!(self.pool.get_num_tokens() < config.max_bound_tokens, "ERR_MAX_TOKENS");
+            assert!(denorm >= config.min_weight, "ERR_MIN_WEIGHT");
+            assert!(denorm <= config.max_weight, "ERR_MAX_WEIGHT");
+            assert!(balance >= config.min_balance, "ERR_MIN_BALANCE");
+            let total_weight = self.math.badd(self.pool.get_total_denormalized_weight(), denorm);
+            assert!(total_weight <= config.max_total_weight, "ERR_MAX_TOTAL_WEIGHT");
+
+            let commit_block = self.env().block_number();
+            self.new_token = Some(NewToken {
+                token,
+                balance,
+                denorm,
+                commit_block,
+            });
+            self.env().emit_event(LogCommitToken {
+                token: Some(token),
+                balance,
+                denorm,
+                commit_block,
+            });
+        }
+
+        #[ink(message)]
+        pub fn apply_add_token(&mut self) {
+            self._lock_();
+            self._only_controller();
+            self._require_created();
+            assert!(self.rights.can_add_remove_tokens, "ERR_CANNOT_ADD_REMOVE_TOKENS");
+            let new_token = self.new_token.take().expect("ERR_NO_TOKEN_COMMIT");
+            let unlock_block = new_token.commit_block + self.add_token_time_lock;
+            assert!(self.env().block_number() >= unlock_block, "ERR_TIMELOCK_STILL_COUNTING");
+
+            let sender = self.env().caller();
+            let total_weight = self.pool.get_total_denormalized_weight();
+            let total_supply = self.token.total_supply();
+            let shares = self.math.bmul(total_supply, self.math.bdiv(new_token.denorm, total_weight));
+
+            self._pull_underlying(new_token.token, sender, new_token.balance);
+            self._bind(new_token.token, new_token.balance, new_token.denorm);
+            self._mint_pool_share_to(sender, shares);
+
+            self.env().emit_event(LogAddToken {
+                token: Some(new_token.token),
+                balance: new_token.balance,
+                denorm: new_token.denorm,
+            });
+            self._unlock_();
+        }
+
+        /// First step of removing a token; `apply_remove_token` unbinds it once
+        /// `add_token_time_lock` blocks have passed.
+        #[ink(message)]
+        pub fn commit_remove_token(&mut self, token: AccountId) {
+            self._only_controller();
+            self._require_created();
+            assert!(self.rights.can_add_remove_tokens, "ERR_CANNOT_ADD_REMOVE_TOKENS");
+            assert!(self.pool.is_bound(token), "ERR_NOT_BOUND");
+
+            let commit_block = self.env().block_number();
+            self.removal = Some((token, commit_block));
+            self.env().emit_event(LogCommitRemoveToken {
+                token: Some(token),
+                commit_block,
+            });
+        }
+
+        /// Unbinds the committed token, burning the controller's LP shares that backed its weight.
+        #[ink(message)]
+        pub fn apply_remove_token(&mut self) {
+            self._lock_();
+            self._only_controller();
+            self._require_created();
+            assert!(self.rights.can_add_remove_tokens, "ERR_CANNOT_ADD_REMOVE_TOKENS");
+            let (token, commit_block) = self.removal.take().expect("ERR_NO_TOKEN_COMMIT");
+            let unlock_block = commit_block + self.add_token_time_lock;
+            assert!(self.env().block_number() >= unlock_block, "ERR_TIMELOCK_STILL_COUNTING");
+            assert!(self.pool.is_bound(token), "ERR_NOT_BOUND");
+
+            let sender = self.env().caller();
+            let total_weight = self.pool.get_total_denormalized_weight();
+            let total_supply = self.token.total_supply();
+            let weight = self.pool.get_denormalized_weight(token);
+            let balance = self.pool.get_balance(token);
+            let shares = self.math.bmul(total_supply, self.math.bdiv(weight, total_weight));
+
+            self._burn_pool_share_from(sender, shares);
+            self.pool.unbind(token);
+            let amount = self._after_exit_fee(balance);
+            self._push_underlying(token, sender, amount);
+
+            self.env().emit_event(LogRemoveToken {
+                token: Some(token),
+            });
+            self._unlock_();
+        }
+
+        #[ink(message)]
+        pub fn whitelist_liquidity_provider(&mut self, account: AccountId) {
+            self._only_controller();
+            assert!(self.rights.can_whitelist_lps, "ERR_CANNOT_WHITELIST_LPS");
+            self.whitelist.insert(account, true);
+            self.env().emit_event(LogWhitelist {
+                account: Some(account),
+                whitelisted: true,
+            });
+        }
+
+        #[ink(message)]
+        pub fn remove_whitelisted_liquidity_provider(&mut self, account: AccountId) {
+            self._only_controller();
+            assert!(self.rights.can_whitelist_lps, "ERR_CANNOT_WHITELIST_LPS");
+            assert!(self.is_whitelisted(account), "ERR_LP_NOT_WHITELISTED");
+            self.whitelist.take(&account);
+            self.env().emit_event(LogWhitelist {
+                account: Some(account),
+                whitelisted: false,
+            });
+        }
+
+        /// Caps the LP token supply reachable through `join_pool`.
+        #[ink(message)]
+        pub fn set_cap(&mut self, cap: u128) {
+            self._only_controller();
+            assert!(self.rights.can_change_cap, "ERR_CANNOT_SET_CAP");
+            self.cap = cap;
+            self.env().emit_event(LogCap {
+                cap,
+            });
+        }
+    }
+}
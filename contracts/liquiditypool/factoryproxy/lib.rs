@@ -0,0 +1,55 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+pub use self::factory::FactoryInterface;
+use ink_lang as ink;
+
+/// Interface of the liquidity pool `Factory` for contracts that cannot
+/// depend on the `factory` crate itself, such as `Pool` and `ExchangeProxy`.
+#[ink::contract]
+mod factory {
+    use ink_prelude::vec::Vec;
+
+    #[ink(storage)]
+    pub struct FactoryInterface {}
+
+    impl FactoryInterface {
+        #[ink(constructor)]
+        pub fn new() -> Self {
+            unimplemented!()
+        }
+
+        #[ink(message)]
+        pub fn is_pool(&self, _b: AccountId) -> bool {
+            unimplemented!()
+        }
+
+        #[ink(message)]
+        pub fn get_token_pool_count(&self, _token: AccountId) -> u32 {
+            unimplemented!()
+        }
+
+        #[ink(message)]
+        pub fn pools_for_token(&self, _token: AccountId, _offset: u32, _limit: u32) -> Vec<AccountId> {
+            unimplemented!()
+        }
+
+        #[ink(message)]
+        pub fn pools_for_pair(&self, _a: AccountId, _b: AccountId) -> Vec<AccountId> {
+            unimplemented!()
+        }
+
+        #[ink(message)]
+        pub fn is_token_blocked(&self, _token: AccountId) -> bool {
+            unimplemented!()
+        }
+
+        #[ink(message)]
+        pub fn pool_token_bound(&mut self, _token: AccountId) {
+            unimplemented!()
+        }
+
+        #[ink(message)]
+        pub fn pool_token_unbound(&mut self, _token: AccountId) {
+            unimplemented!()
+        }
+    }
+}
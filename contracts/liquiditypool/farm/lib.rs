@@ -0,0 +1,430 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+pub use self::farm::Farm;
+use ink_lang as ink;
+
+#[ink::contract]
+mod farm {
+    use ink_prelude::vec::Vec;
+    use ink_storage::{
+        collections::{
+            HashMap as StorageHashMap,
+            Vec as StorageVec,
+        },
+        traits::{PackedLayout, SpreadLayout},
+        Lazy,
+    };
+    use ink_env::call::FromAccountId;
+
+    use math::Math;
+    use token::Token;
+    use pool::Pool;
+    use factoryproxy::FactoryInterface;
+    use cdot::PAT;
+
+    #[derive(
+    Debug, PartialEq, Eq, Clone, scale::Encode, scale::Decode, SpreadLayout, PackedLayout,
+    )]
+    #[cfg_attr(
+    feature = "std",
+    derive(scale_info::TypeInfo, ink_storage::traits::StorageLayout)
+    )]
+    pub struct PoolInfo {
+        pub lp_token: AccountId,
+        pub alloc_point: u128,
+        pub last_reward_block: BlockNumber,
+        pub acc_reward_per_share: u128,   // accumulated rewards per LP share, times ACC_PRECISION
+        pub total_staked: u128,
+    }
+
+    #[derive(
+    Debug, PartialEq, Eq, Clone, Default, scale::Encode, scale::Decode, SpreadLayout, PackedLayout,
+    )]
+    #[cfg_attr(
+    feature = "std",
+    derive(scale_info::TypeInfo, ink_storage::traits::StorageLayout)
+    )]
+    pub struct UserInfo {
+        pub amount: u128,
+        pub reward_debt: u128,
+        pub unpaid: u128,   // rewards earned but not paid while the farm was underfunded
+    }
+
+    /// Scale of `acc_reward_per_share`; finer than `BONE` so small per-share
+    /// rewards do not round to zero.
+    pub const ACC_PRECISION: u128 = 1000000000000000000;
+
+    #[ink(storage)]
+    pub struct Farm {
+        admin: AccountId,
+        factory: Lazy<FactoryInterface>,
+        math: Lazy<Math>,
+        reward_token: Lazy<PAT>,
+
+        reward_per_block: u128,
+        start_block: BlockNumber,
+        total_alloc_point: u128,
+
+        pool_list: StorageVec<AccountId>,
+        pools: StorageHashMap<AccountId, PoolInfo>,
+        users: StorageHashMap<(AccountId, AccountId), UserInfo>,
+    }
+
+    #[ink(event)]
+    pub struct LogAddPool {
+        #[ink(topic)]
+        pool: Option<AccountId>,
+        alloc_point: u128,
+    }
+
+    #[ink(event)]
+    pub struct LogSetPool {
+        #[ink(topic)]
+        pool: Option<AccountId>,
+        alloc_point: u128,
+    }
+
+    #[ink(event)]
+    pub struct LogRewardRate {
+        #[ink(topic)]
+        caller: Option<AccountId>,
+        reward_per_block: u128,
+    }
+
+    #[ink(event)]
+    pub struct LogDeposit {
+        #[ink(topic)]
+        user: Option<AccountId>,
+        #[ink(topic)]
+        pool: Option<AccountId>,
+        amount: u128,
+    }
+
+    #[ink(event)]
+    pub struct LogWithdraw {
+        #[ink(topic)]
+        user: Option<AccountId>,
+        #[ink(topic)]
+        pool: Option<AccountId>,
+        amount: u128,
+    }
+
+    #[ink(event)]
+    pub struct LogClaim {
+        #[ink(topic)]
+        user: Option<AccountId>,
+        #[ink(topic)]
+        pool: Option<AccountId>,
+        amount: u128,
+    }
+
+    #[ink(event)]
+    pub struct LogEmergencyWithdraw {
+        #[ink(topic)]
+        user: Option<AccountId>,
+        #[ink(topic)]
+        pool: Option<AccountId>,
+        amount: u128,
+    }
+
+    impl Farm {
+        #[ink(constructor)]
+        pub fn new(factory_address: AccountId,
+                   math_address: AccountId,
+                   reward_token: AccountId,
+                   reward_per_block: u128,
+                   start_block: BlockNumber) -> Self {
+            let factory: FactoryInterface = FromAccountId::from_account_id(factory_address);
+            let math: Math = FromAccountId::from_account_id(math_address);
+            let reward: PAT = FromAccountId::from_account_id(reward_token);
+            Self {
+                admin: Self::env().caller(),
+                factory: Lazy::new(factory),
+                math: Lazy::new(math),
+                reward_token: Lazy::new(reward),
+
+                reward_per_block,
+                start_block,
+                total_alloc_point: 0,
+
+                pool_list: StorageVec::new(),
+                pools: StorageHashMap::new(),
+                users: StorageHashMap::new(),
+            }
+        }
+
+        fn _only_admin(&self) {
+            assert!(self.env().caller() == self.admin, "ERR_NOT_ADMIN");
+        }
+
+        fn _get_pool(&self, pool: AccountId) -> PoolInfo {
+            return self.pools.get(&pool).cloned().expect("ERR_POOL_NOT_ADDED");
+        }
+
+        fn _get_user(&self, pool: AccountId, user: AccountId) -> UserInfo {
+            return self.users.get(&(pool, user)).cloned().unwrap_or_default();
+        }
+
+        fn _reward_debt(&self, amount: u128, acc_reward_per_share: u128) -> u128 {
+            return amount.checked_mul(acc_reward_per_share).expect("ERR_MUL_OVERFLOW") / ACC_PRECISION;
+        }
+
+        // acc_reward_per_share as of the current block, without writing it back
+        fn _current_acc(&self, info: &PoolInfo) -> u128 {
+            let block = self.env().block_number();
+            if block <= info.last_reward_block || info.total_staked == 0 || self.total_alloc_point == 0 {
+                return info.acc_reward_per_share;
+            }
+
+            let blocks = u128::from(block - info.last_reward_block);
+            let share = self.math.bdiv(info.alloc_point, self.total_alloc_point);
+            let total = blocks.checked_mul(self.reward_per_block).expect("ERR_MUL_OVERFLOW");
+            let reward = self.math.bmul(total, share);
+            let per_share = reward.checked_mul(ACC_PRECISION).expect("ERR_MUL_OVERFLOW") / info.total_staked;
+            return self.math.badd(info.acc_reward_per_share, per_share);
+        }
+
+        fn _update_pool(&mut self, pool: AccountId) -> PoolInfo {
+            let mut info = self._get_pool(pool);
+            let block = self.env().block_number();
+            if block <= info.last_reward_block {
+                return info;
+            }
+
+            info.acc_reward_per_share = self._current_acc(&info);
+            info.last_reward_block = block;
+            self.pools.insert(pool, info.clone());
+            return info;
+        }
+
+        fn _mass_update_pools(&mut self) {
+            let list: Vec<AccountId> = self.pool_list.iter().copied().collect();
+            for pool in list {
+                self._update_pool(pool);
+            }
+        }
+
+        // Rewards are paid from the farm's pre-funded balance; never more than it holds.
+        // Returns the amount actually paid.
+        fn _safe_reward_transfer(&mut self, to: AccountId, amount: u128) -> u128 {
+            let balance = self.reward_token.balance_of(self.env().account_id());
+            let paid = if amount > balance { balance } else { amount };
+            if paid > 0 {
+                assert!(self.reward_token.transfer(to, paid).is_ok(), "ERR_REWARD_TRANSFER");
+            }
+            return paid;
+        }
+
+        // Pays the user's pending rewards plus whatever was left unpaid before; the part
+        // the farm cannot cover yet is kept in `u.unpaid` and paid on a later harvest.
+        fn _harvest(&mut self, pool: AccountId, user: AccountId, info: &PoolInfo, u: &mut UserInfo) {
+            let accumulated = self._reward_debt(u.amount, info.acc_reward_per_share);
+            let pending = self.math.badd(self.math.bsub(accumulated, u.reward_debt), u.unpaid);
+            if pending > 0 {
+                let paid = self._safe_reward_transfer(user, pending);
+                u.unpaid = self.math.bsub(pending, paid);
+                self.env().emit_event(LogClaim {
+                    user: Some(user),
+                    pool: Some(pool),
+                    amount: paid,
+                });
+            }
+        }
+
+        #[ink(message)]
+        pub fn get_admin(&self) -> AccountId {
+            self.admin
+        }
+
+        #[ink(message)]
+        pub fn set_admin(&mut self, admin: AccountId) {
+            self._only_admin();
+            self.admin = admin;
+        }
+
+        #[ink(message)]
+        pub fn get_pools(&self) -> Vec<AccountId> {
+            return self.pool_list.iter().copied().collect();
+        }
+
+        #[ink(message)]
+        pub fn get_pool_info(&self, pool: AccountId) -> Option<PoolInfo> {
+            return self.pools.get(&pool).cloned();
+        }
+
+        #[ink(message)]
+        pub fn get_user_info(&self, pool: AccountId, user: AccountId) -> UserInfo {
+            return self._get_user(pool, user);
+        }
+
+        #[ink(message)]
+        pub fn get_reward_per_block(&self) -> u128 {
+            self.reward_per_block
+        }
+
+        #[ink(message)]
+        pub fn get_total_alloc_point(&self) -> u128 {
+            self.total_alloc_point
+        }
+
+        #[ink(message)]
+        pub fn pending_reward(&self, pool: AccountId, user: AccountId) -> u128 {
+            let info = self._get_pool(pool);
+            let u = self._get_user(pool, user);
+            let acc = self._current_acc(&info);
+            let pending = self.math.bsub(self._reward_debt(u.amount, acc), u.reward_debt);
+            return self.math.badd(pending, u.unpaid);
+        }
+
+        /// Starts rewarding LP shares of a factory pool with `alloc_point` weight.
+        #[ink(message)]
+        pub fn add_pool(&mut self, pool: AccountId, alloc_point: u128) {
+            self._only_admin();
+            assert!(self.factory.is_pool(pool), "ERR_NOT_POOL");
+            assert!(!self.pools.contains_key(&pool), "ERR_POOL_EXISTS");
+            self._mass_update_pools();
+
+            let p: Pool = FromAccountId::from_account_id(pool);
+            let block = self.env().block_number();
+            let last_reward_block = if block > self.start_block { block } else { self.start_block };
+            self.total_alloc_point = self.math.badd(self.total_alloc_point, alloc_point);
+            self.pools.insert(pool, PoolInfo {
+                lp_token: p.get_token(),
+                alloc_point,
+                last_reward_block,
+                acc_reward_per_share: 0,
+                total_staked: 0,
+            });
+            self.pool_list.push(pool);
+
+            self.env().emit_event(LogAddPool {
+                pool: Some(pool),
+                alloc_point,
+            });
+        }
+
+        #[ink(message)]
+        pub fn set_alloc_point(&mut self, pool: AccountId, alloc_point: u128) {
+            self._only_admin();
+            self._mass_update_pools();
+
+            let mut info = self._get_pool(pool);
+            self.total_alloc_point = self.math.badd(self.math.bsub(self.total_alloc_point, info.alloc_point), alloc_point);
+            info.alloc_point = alloc_point;
+            self.pools.insert(pool, info);
+
+            self.env().emit_event(LogSetPool {
+                pool: Some(pool),
+                alloc_point,
+            });
+        }
+
+        #[ink(message)]
+        pub fn set_reward_per_block(&mut self, reward_per_block: u128) {
+            self._only_admin();
+            self._mass_update_pools();
+            self.reward_per_block = reward_per_block;
+
+            self.env().emit_event(LogRewardRate {
+                caller: Some(self.env().caller()),
+                reward_per_block,
+            });
+        }
+
+        #[ink(message)]
+        pub fn update_pool(&mut self, pool: AccountId) {
+            self._update_pool(pool);
+        }
+
+        #[ink(message)]
+        pub fn mass_update_pools(&mut self) {
+            self._mass_update_pools();
+        }
+
+        /// Stakes `amount` LP shares of `pool`; the caller must have approved this contract.
+        #[ink(message)]
+        pub fn deposit(&mut self, pool: AccountId, amount: u128) {
+            let sender = self.env().caller();
+            let this = self.env().account_id();
+            let mut info = self._update_pool(pool);
+            let mut u = self._get_user(pool, sender);
+            self._harvest(pool, sender, &info, &mut u);
+
+            if amount > 0 {
+                let mut lp: Token = FromAccountId::from_account_id(info.lp_token);
+                assert!(lp.transfer_from(sender, this, amount).is_ok(), "ERR_LP_TRANSFER");
+                u.amount = self.math.badd(u.amount, amount);
+                info.total_staked = self.math.badd(info.total_staked, amount);
+                self.pools.insert(pool, info.clone());
+            }
+            u.reward_debt = self._reward_debt(u.amount, info.acc_reward_per_share);
+            self.users.insert((pool, sender), u);
+
+            self.env().emit_event(LogDeposit {
+                user: Some(sender),
+                pool: Some(pool),
+                amount,
+            });
+        }
+
+        #[ink(message)]
+        pub fn withdraw(&mut self, pool: AccountId, amount: u128) {
+            let sender = self.env().caller();
+            let mut info = self._update_pool(pool);
+            let mut u = self._get_user(pool, sender);
+            assert!(u.amount >= amount, "ERR_INSUFFICIENT_BAL");
+            self._harvest(pool, sender, &info, &mut u);
+
+            if amount > 0 {
+                u.amount = self.math.bsub(u.amount, amount);
+                info.total_staked = self.math.bsub(info.total_staked, amount);
+                self.pools.insert(pool, info.clone());
+                let mut lp: Token = FromAccountId::from_account_id(info.lp_token);
+                assert!(lp.transfer(sender, amount).is_ok(), "ERR_LP_TRANSFER");
+            }
+            u.reward_debt = self._reward_debt(u.amount, info.acc_reward_per_share);
+            self.users.insert((pool, sender), u);
+
+            self.env().emit_event(LogWithdraw {
+                user: Some(sender),
+                pool: Some(pool),
+                amount,
+            });
+        }
+
+        #[ink(message)]
+        pub fn claim(&mut self, pool: AccountId) {
+            let sender = self.env().caller();
+            let info = self._update_pool(pool);
+            let mut u = self._get_user(pool, sender);
+            self._harvest(pool, sender, &info, &mut u);
+
+            u.reward_debt = self._reward_debt(u.amount, info.acc_reward_per_share);
+            self.users.insert((pool, sender), u);
+        }
+
+        /// Returns the caller's LP shares without paying rewards; unpaid rewards are forfeited.
+        #[ink(message)]
+        pub fn emergency_withdraw(&mut self, pool: AccountId) {
+            let sender = self.env().caller();
+            let mut info = self._get_pool(pool);
+            let u = self._get_user(pool, sender);
+            let amount = u.amount;
+
+            info.total_staked = self.math.bsub(info.total_staked, amount);
+            self.pools.insert(pool, info.clone());
+            self.users.insert((pool, sender), UserInfo::default());
+
+            if amount > 0 {
+                let mut lp: Token = FromAccountId::from_account_id(info.lp_token);
+                assert!(lp.transfer(sender, amount).is_ok(), "ERR_LP_TRANSFER");
+            }
+
+            self.env().emit_event(LogEmergencyWithdraw {
+                user: Some(sender),
+                pool: Some(pool),
+                amount,
+            });
+        }
+    }
+}
@@ -18,16 +18,91 @@ use ink_lang as ink;
 
 #[ink::contract]
 mod factory {
-    use ink_storage::collections::HashMap as StorageHashMap;
-    use ink_lang::ToAccountId;
+    use ink_storage::{
+        collections::{
+            HashMap as StorageHashMap,
+            Vec as StorageVec,
+        },
+        traits::{PackedLayout, SpreadLayout},
+    };
+    use ink_lang::{ForwardCallMut, ToAccountId};
     use ink_env::call::FromAccountId;
     use ink_env::debug_println;
+    use ink_env::hash::Blake2x256;
     use ink_prelude::string::String;
+    use ink_prelude::vec;
+    use ink_prelude::vec::Vec;
 
     use math::Math;
+    use math::{
+        BONE,
+        INIT_POOL_SUPPLY,
+        MIN_FEE,
+        MAX_FEE,
+    };
     use base::Base;
     use token::Token;
-    use pool::Pool;
+    use pool::{Pool, PoolConfig, STORAGE_VERSION};
+    use cdot::PAT;
+
+    /// Blocks between `announce_upgrade` and the `upgrade_pool` it allows, so LPs can exit first.
+    pub const UPGRADE_DELAY: BlockNumber = 14400;
+
+    /// Token and pool code used for pools created while this version is current.
+    #[derive(
+    Debug, PartialEq, Eq, Clone, scale::Encode, scale::Decode, SpreadLayout, PackedLayout,
+    )]
+    #[cfg_attr(
+    feature = "std",
+    derive(scale_info::TypeInfo, ink_storage::traits::StorageLayout)
+    )]
+    pub struct CodeVersion {
+        pub token_code_hash: Hash,
+        pub pool_code_hash: Hash,
+        pub storage_version: u32,   // `pool::STORAGE_VERSION` of the pool code
+    }
+
+    #[derive(
+    Debug, PartialEq, Eq, Clone, Copy, scale::Encode, scale::Decode, SpreadLayout, PackedLayout,
+    )]
+    #[cfg_attr(
+    feature = "std",
+    derive(scale_info::TypeInfo, ink_storage::traits::StorageLayout)
+    )]
+    pub enum TokenStatus {
+        Unverified,
+        Verified,
+        Blocked,
+    }
+
+    /// Token registry entry, with metadata cached from the token's `PAT` interface.
+    #[derive(
+    Debug, PartialEq, Eq, Clone, scale::Encode, scale::Decode, SpreadLayout, PackedLayout,
+    )]
+    #[cfg_attr(
+    feature = "std",
+    derive(scale_info::TypeInfo, ink_storage::traits::StorageLayout)
+    )]
+    pub struct TokenInfo {
+        pub status: TokenStatus,
+        pub name: Option<String>,
+        pub symbol: Option<String>,
+        pub decimals: Option<u8>,
+        pub price_feed: Option<AccountId>,
+    }
+
+    /// A swap fee new pools can be created with.
+    #[derive(
+    Debug, PartialEq, Eq, Clone, scale::Encode, scale::Decode, SpreadLayout, PackedLayout,
+    )]
+    #[cfg_attr(
+    feature = "std",
+    derive(scale_info::TypeInfo, ink_storage::traits::StorageLayout)
+    )]
+    pub struct FeeTier {
+        pub fee: u128,
+        pub enabled: bool,
+    }
 
     #[ink(storage)]
     pub struct Factory {
@@ -36,9 +111,40 @@ mod factory {
 
         token_code_hash: Hash,
         pool_code_hash: Hash,
+        code_versions: StorageVec<CodeVersion>,
+        pool_versions: StorageHashMap<AccountId, u32>,
+        pending_upgrades: StorageHashMap<AccountId, (u32, BlockNumber)>,   // version, announced at
 
         is_pool: StorageHashMap<AccountId, bool>,
+        deregistered: StorageHashMap<AccountId, bool>,
+        used_salts: StorageHashMap<u32, bool>,
+        pools: StorageVec<AccountId>,
+        // per token, its pools at indices 0..token_pool_count, and the index of each pool
+        token_pool_count: StorageHashMap<AccountId, u32>,
+        token_pool_at: StorageHashMap<(AccountId, u32), AccountId>,
+        token_pool_index: StorageHashMap<(AccountId, AccountId), u32>,
+        fee_tiers: StorageVec<FeeTier>,    // tier id is the index
+        pool_tiers: StorageHashMap<AccountId, u32>,
+        token_registry: StorageHashMap<AccountId, TokenInfo>,
+        listed_tokens: StorageVec<AccountId>,
         labs: AccountId,
+        pending_labs: Option<AccountId>,
+        treasury: Option<AccountId>,
+        collect_exit: bool,
+
+        // paid to `labs` on pool creation; in native balance while `creation_fee_token` is None
+        creation_fee: u128,
+        creation_fee_token: Option<AccountId>,
+        // at most `creation_limit` pools per account every `creation_period` blocks, off while 0
+        creation_limit: u32,
+        creation_period: BlockNumber,
+        creations: StorageHashMap<AccountId, (BlockNumber, u32)>,
+
+        // M-of-N admin mode, off while `admin_threshold` is 0
+        admin_signers: Vec<AccountId>,
+        admin_threshold: u32,
+        admin_nonce: u32,
+        approvals: StorageHashMap<Hash, Vec<AccountId>>,
     }
 
     #[ink(event)]
@@ -49,6 +155,34 @@ mod factory {
         pool: Option<AccountId>,
     }
 
+    #[ink(event)]
+    pub struct LogCodeVersion {
+        #[ink(topic)]
+        version: u32,
+        token_code_hash: Hash,
+        pool_code_hash: Hash,
+        storage_version: u32,
+    }
+
+    #[ink(event)]
+    pub struct LogAnnounceUpgrade {
+        #[ink(topic)]
+        pool: Option<AccountId>,
+        #[ink(topic)]
+        version: u32,
+        executable_at: BlockNumber,
+    }
+
+    #[ink(event)]
+    pub struct LogUpgradePool {
+        #[ink(topic)]
+        pool: Option<AccountId>,
+        #[ink(topic)]
+        new_pool: Option<AccountId>,
+        #[ink(topic)]
+        version: u32,
+    }
+
     #[ink(event)]
     pub struct LogLabs {
         #[ink(topic)]
@@ -57,6 +191,95 @@ mod factory {
         labs: Option<AccountId>,
     }
 
+    #[ink(event)]
+    pub struct LogFeeTier {
+        #[ink(topic)]
+        tier: u32,
+        fee: u128,
+        enabled: bool,
+    }
+
+    #[ink(event)]
+    pub struct LogTokenListing {
+        #[ink(topic)]
+        token: Option<AccountId>,
+        status: TokenStatus,
+        price_feed: Option<AccountId>,
+    }
+
+    #[ink(event)]
+    pub struct LogCreationFee {
+        #[ink(topic)]
+        token: Option<AccountId>,
+        fee: u128,
+    }
+
+    #[ink(event)]
+    pub struct LogCreationLimit {
+        limit: u32,
+        period: BlockNumber,
+    }
+
+    #[ink(event)]
+    pub struct LogDeregisterPool {
+        #[ink(topic)]
+        caller: Option<AccountId>,
+        #[ink(topic)]
+        pool: Option<AccountId>,
+    }
+
+    #[ink(event)]
+    pub struct LogCollect {
+        #[ink(topic)]
+        pool: Option<AccountId>,
+        #[ink(topic)]
+        treasury: Option<AccountId>,
+        shares: u128,
+        tokens: Vec<AccountId>,
+        amounts: Vec<u128>,
+    }
+
+    #[ink(event)]
+    pub struct LogLabsProposed {
+        #[ink(topic)]
+        caller: Option<AccountId>,
+        #[ink(topic)]
+        labs: Option<AccountId>,
+    }
+
+    #[ink(event)]
+    pub struct LogLabsCancelled {
+        #[ink(topic)]
+        caller: Option<AccountId>,
+        #[ink(topic)]
+        labs: Option<AccountId>,
+    }
+
+    #[ink(event)]
+    pub struct LogAdminSigners {
+        #[ink(topic)]
+        caller: Option<AccountId>,
+        signers: Vec<AccountId>,
+        threshold: u32,
+    }
+
+    #[ink(event)]
+    pub struct LogAdminApproval {
+        #[ink(topic)]
+        signer: Option<AccountId>,
+        #[ink(topic)]
+        action: Hash,
+        approvals: u32,
+    }
+
+    #[ink(event)]
+    pub struct LogAdminExecuted {
+        #[ink(topic)]
+        caller: Option<AccountId>,
+        #[ink(topic)]
+        action: Hash,
+    }
+
     impl Factory {
         #[ink(constructor)]
         pub fn new(
@@ -66,34 +289,172 @@ mod factory {
                    pool_code_hash: Hash) -> Self {
             let is_pool = StorageHashMap::new();
             let labs = Self::env().caller();
+            // 0.05%, 0.3% and 1%
+            let mut fee_tiers = StorageVec::new();
+            for fee in [BONE / 2000, BONE * 3 / 1000, BONE / 100].iter() {
+                fee_tiers.push(FeeTier {
+                    fee: *fee,
+                    enabled: true,
+                });
+            }
+            let mut code_versions = StorageVec::new();
+            code_versions.push(CodeVersion {
+                token_code_hash,
+                pool_code_hash,
+                storage_version: STORAGE_VERSION,
+            });
             Self {
                 math_address,
                 base_address,
 
                 token_code_hash,
                 pool_code_hash,
+                code_versions,
+                pool_versions: StorageHashMap::new(),
+                pending_upgrades: StorageHashMap::new(),
 
                 is_pool,
+                deregistered: StorageHashMap::new(),
+                used_salts: StorageHashMap::new(),
+                pools: StorageVec::new(),
+                token_pool_count: StorageHashMap::new(),
+                token_pool_at: StorageHashMap::new(),
+                token_pool_index: StorageHashMap::new(),
+                fee_tiers,
+                pool_tiers: StorageHashMap::new(),
+                token_registry: StorageHashMap::new(),
+                listed_tokens: StorageVec::new(),
                 labs,
+                pending_labs: None,
+                treasury: None,
+                collect_exit: false,
+
+                creation_fee: 0,
+                creation_fee_token: None,
+                creation_limit: 0,
+                creation_period: 0,
+                creations: StorageHashMap::new(),
+
+                admin_signers: Vec::new(),
+                admin_threshold: 0,
+                admin_nonce: 0,
+                approvals: StorageHashMap::new(),
             }
         }
 
+        /// False for pools flagged by `deregister_pool`.
         #[ink(message)]
         pub fn is_pool(&self, b: AccountId) -> bool {
-            return self.is_pool.get(&b).copied().unwrap_or(false);
+            return self._is_created_pool(b) && !self.is_deregistered(b);
         }
 
         #[ink(message)]
+        pub fn is_deregistered(&self, pool: AccountId) -> bool {
+            return self.deregistered.get(&pool).copied().unwrap_or(false);
+        }
+
+        // created by this factory, deregistered or not
+        fn _is_created_pool(&self, b: AccountId) -> bool {
+            return self.is_pool.get(&b).copied().unwrap_or(false);
+        }
+
+        /// Creates a pool charging the swap fee of `tier`; the caller becomes its controller.
+        /// The token and pool endowments, plus a native creation fee if any, must be sent exactly.
+        #[ink(message, payable)]
         pub fn new_pool(&mut self,  salt: u32,
+                        tier: u32,
                         token_endowment: u128,
-                        pool_endowment: u128) -> AccountId {
+                        pool_endowment: u128,
+                        config: Option<PoolConfig>,
+                        name: Option<String>,
+                        symbol: Option<String>,
+                        decimals: Option<u8>) -> AccountId {
+            let sender = Self::env().caller();
+            let pool_address = self._create_pool(salt, tier, token_endowment, pool_endowment, config, name, symbol, decimals);
+
+            let mut p: Pool = FromAccountId::from_account_id(pool_address);
+            p.set_registered();
+            p.set_controller(sender);
+
+            debug_println!("new pool succeed");
+            return pool_address
+        }
+
+        /// Creates a pool, binds `tokens` with the given balances and weights pulled
+        /// from the caller, sets the swap fee of `tier`, finalizes it and hands the initial LP
+        /// shares and control of the pool to the caller, all in one transaction.
+        /// The caller must have approved each balance to this factory. The token and pool
+        /// endowments, plus a native creation fee if any, must be sent exactly.
+        #[ink(message, payable)]
+        pub fn new_finalized_pool(&mut self, salt: u32,
+                                  tokens: Vec<AccountId>,
+                                  balances: Vec<u128>,
+                                  weights: Vec<u128>,
+                                  tier: u32,
+                                  token_endowment: u128,
+                                  pool_endowment: u128,
+                                  config: Option<PoolConfig>,
+                                  name: Option<String>,
+                                  symbol: Option<String>,
+                                  decimals: Option<u8>) -> AccountId {
+            assert!(tokens.len() == balances.len() && tokens.len() == weights.len(), "ERR_LENGTH_MISMATCH");
+            let sender = Self::env().caller();
+            let this = self.env().account_id();
+            let pool_address = self._create_pool(salt, tier, token_endowment, pool_endowment, config, name, symbol, decimals);
+
+            let mut p: Pool = FromAccountId::from_account_id(pool_address);
+            for i in 0..tokens.len() {
+                // not yet registered, so the pool cannot check this itself
+                assert!(!self.is_token_blocked(tokens[i]), "ERR_TOKEN_BLOCKED");
+                let mut erc: PAT = FromAccountId::from_account_id(tokens[i]);
+                assert!(erc.transfer_from(sender, this, balances[i]).is_ok(), "ERR_ERC20_FALSE");
+                assert!(erc.approve(pool_address, balances[i]).is_ok(), "ERR_ERC20_FALSE");
+                p.bind(tokens[i], balances[i], weights[i]);
+            }
+            p.finalize();
+
+            // the pool is registered only now, so its binds above did not call back
+            // into this factory; index its tokens directly instead
+            p.set_registered();
+            for t in tokens.iter() {
+                self._add_token_pool(*t, pool_address);
+            }
+
+            let mut lp: Token = FromAccountId::from_account_id(p.get_token());
+            assert!(lp.transfer(sender, INIT_POOL_SUPPLY).is_ok(), "ERR_TOKEN_FAILED");
+            p.set_controller(sender);
+
+            debug_println!("new finalized pool succeed");
+            return pool_address
+        }
+
+        // Instantiates the token and pool contracts and registers the pool;
+        // the factory stays the pool's controller.
+        fn _create_pool(&mut self,  salt: u32,
+                        tier: u32,
+                        token_endowment: u128,
+                        pool_endowment: u128,
+                        config: Option<PoolConfig>,
+                        name: Option<String>,
+                        symbol: Option<String>,
+                        decimals: Option<u8>) -> AccountId {
+            let config = config.unwrap_or_default();
+            config.validate();
+            let tier_fee = self.fee_tiers.get(tier).cloned().expect("ERR_NO_FEE_TIER");
+            assert!(tier_fee.enabled, "ERR_FEE_TIER_DISABLED");
+            let endowment = token_endowment.checked_add(pool_endowment).expect("ERR_ADD_OVERFLOW");
+            self._charge_creation(Self::env().caller(), endowment);
+
+            assert!(!self.is_salt_used(salt), "ERR_SALT_USED");
+            self.used_salts.insert(salt, true);
+
             let salt_bytes = salt.to_le_bytes();
             debug_println!("enter ");
             assert_ne!(self.token_code_hash, Hash::from([0; 32]));
             assert_ne!(self.math_address, Default::default());
             debug_println!("token code hash and math address valid ");
 
-            let token_params = Token::new(self.math_address)
+            let token_params = Token::new(self.math_address, name, symbol, decimals)
                 .endowment(token_endowment)
                 .code_hash(self.token_code_hash)
                 .salt_bytes(salt_bytes)
@@ -108,7 +469,7 @@ mod factory {
 
             debug_println!("instantiate token succeed");
 
-            let pool_params = Pool::new(self.math_address, self.base_address, token_address)
+            let pool_params = Pool::new(self.math_address, self.base_address, token_address, config)
                 .endowment(pool_endowment)
                 .code_hash(self.pool_code_hash)
                 .salt_bytes(salt_bytes)
@@ -124,45 +485,747 @@ mod factory {
             let sender = Self::env().caller();
             let mut t: Token = FromAccountId::from_account_id(token_address);
             t.set_controller(pool_address);
-
             let mut p: Pool = FromAccountId::from_account_id(pool_address);
-            p.set_controller(sender);
+            p.set_swap_fee(tier_fee.fee);
+            p.lock_swap_fee();
+            self.pool_tiers.insert(pool_address, tier);
+
             self.is_pool.insert(pool_address, true);
+            self.pools.push(pool_address);
+            self.pool_versions.insert(pool_address, self.get_current_version());
 
             self.env().emit_event(LogNewPool {
                 caller: Some(sender),
                 pool: Some(pool_address),
             });
 
-            debug_println!("new pool succeed");
             return pool_address
         }
 
+        // Takes the creation fee, if any, and counts the pool against the
+        // creator's rate limit. `endowment` is what the new contracts are endowed with;
+        // the caller sends it along, so it never comes out of this factory's balance.
+        fn _charge_creation(&mut self, sender: AccountId, endowment: u128) {
+            if self.creation_limit > 0 {
+                let block = self.env().block_number();
+                let (start, count) = self.creations.get(&sender).copied().unwrap_or((block, 0));
+                let (start, count) = if block >= start + self.creation_period { (block, 0) } else { (start, count) };
+                assert!(count < self.creation_limit, "ERR_CREATION_LIMIT");
+                self.creations.insert(sender, (start, count + 1));
+            }
+
+            // exactly the endowments and the native fee must be sent, so no value is
+            // left behind in the factory
+            let value = self.env().transferred_balance();
+            match self.creation_fee_token {
+                None => {
+                    let fee = self.creation_fee;
+                    assert!(Some(value) == endowment.checked_add(fee), "ERR_CREATION_FEE");
+                    if fee > 0 {
+                        self.env().transfer(self.labs, fee).expect("ERR_CREATION_FEE");
+                    }
+                }
+                Some(token) => {
+                    assert!(value == endowment, "ERR_CREATION_FEE");
+                    if self.creation_fee > 0 {
+                        let mut erc: PAT = FromAccountId::from_account_id(token);
+                        let r = erc.transfer_from(sender, self.labs, self.creation_fee).is_ok();
+                        assert!(r, "ERR_CREATION_FEE");
+                    }
+                }
+            }
+        }
+
+        #[ink(message)]
+        pub fn get_fee_tiers(&self) -> Vec<FeeTier> {
+            return self.fee_tiers.iter().cloned().collect();
+        }
+
+        /// The fee tier a pool was created with; its swap fee is locked to the tier's fee.
+        #[ink(message)]
+        pub fn get_pool_tier(&self, pool: AccountId) -> Option<u32> {
+            return self.pool_tiers.get(&pool).copied();
+        }
+
+        #[ink(message)]
+        pub fn add_fee_tier(&mut self, fee: u128) -> Option<u32> {
+            if !self._admin(b"add_fee_tier", fee) {
+                return None;
+            }
+            assert!(fee >= MIN_FEE && fee <= MAX_FEE, "ERR_INVALID_FEE");
+            assert!(!self.fee_tiers.iter().any(|t| t.fee == fee), "ERR_FEE_TIER_EXISTS");
+            self.fee_tiers.push(FeeTier {
+                fee,
+                enabled: true,
+            });
+
+            let tier = self.fee_tiers.len() - 1;
+            self.env().emit_event(LogFeeTier {
+                tier,
+                fee,
+                enabled: true,
+            });
+            return Some(tier);
+        }
+
+        /// Disabled tiers cannot be used for new pools; existing pools keep their fee.
+        #[ink(message)]
+        pub fn set_fee_tier_enabled(&mut self, tier: u32, enabled: bool) {
+            if !self._admin(b"set_fee_tier_enabled", (tier, enabled)) {
+                return;
+            }
+            let t = self.fee_tiers.get_mut(tier).expect("ERR_NO_FEE_TIER");
+            t.enabled = enabled;
+            let fee = t.fee;
+            self.env().emit_event(LogFeeTier {
+                tier,
+                fee,
+                enabled,
+            });
+        }
+
+        #[ink(message)]
+        pub fn get_token_info(&self, token: AccountId) -> Option<TokenInfo> {
+            return self.token_registry.get(&token).cloned();
+        }
+
+        /// Unlisted tokens are `Unverified`.
+        #[ink(message)]
+        pub fn get_token_status(&self, token: AccountId) -> TokenStatus {
+            return self.token_registry.get(&token).map(|t| t.status).unwrap_or(TokenStatus::Unverified);
+        }
+
+        #[ink(message)]
+        pub fn is_token_blocked(&self, token: AccountId) -> bool {
+            return self.get_token_status(token) == TokenStatus::Blocked;
+        }
+
+        /// Listed tokens in listing order, `limit` at most, starting at `offset`.
+        #[ink(message)]
+        pub fn get_listed_tokens(&self, offset: u32, limit: u32) -> Vec<AccountId> {
+            return self.listed_tokens.iter().skip(offset as usize).take(limit as usize).copied().collect();
+        }
+
+        /// Lists `token` or changes its listing. The token itself is never called here,
+        /// so even a token whose getters fail can be blocked; its name, symbol and
+        /// decimals are cached by `refresh_token_metadata`.
+        #[ink(message)]
+        pub fn list_token(&mut self, token: AccountId, status: TokenStatus, price_feed: Option<AccountId>) {
+            if !self._admin(b"list_token", (token, status, price_feed)) {
+                return;
+            }
+            match self.token_registry.get_mut(&token) {
+                Some(info) => {
+                    info.status = status;
+                    info.price_feed = price_feed;
+                }
+                None => {
+                    self.listed_tokens.push(token);
+                    self.token_registry.insert(token, TokenInfo {
+                        status,
+                        name: None,
+                        symbol: None,
+                        decimals: None,
+                        price_feed,
+                    });
+                }
+            }
+            self.env().emit_event(LogTokenListing {
+                token: Some(token),
+                status,
+                price_feed,
+            });
+        }
+
+        /// Re-reads the cached metadata of a listed token; anyone may call it.
+        #[ink(message)]
+        pub fn refresh_token_metadata(&mut self, token: AccountId) {
+            let erc: PAT = FromAccountId::from_account_id(token);
+            let info = self.token_registry.get_mut(&token).expect("ERR_NOT_LISTED");
+            info.name = erc.token_name();
+            info.symbol = erc.token_symbol();
+            info.decimals = erc.token_decimals();
+        }
+
+        #[ink(message)]
+        pub fn get_creation_fee(&self) -> (Option<AccountId>, u128) {
+            (self.creation_fee_token, self.creation_fee)
+        }
+
+        /// Charges `fee` for each new pool, in `token` or in native balance sent
+        /// with the call when `token` is None. A fee of 0 turns it off.
+        #[ink(message)]
+        pub fn set_creation_fee(&mut self, token: Option<AccountId>, fee: u128) {
+            if !self._admin(b"set_creation_fee", (token, fee)) {
+                return;
+            }
+            self.creation_fee_token = token;
+            self.creation_fee = fee;
+            self.env().emit_event(LogCreationFee {
+                token,
+                fee,
+            });
+        }
+
+        #[ink(message)]
+        pub fn get_creation_limit(&self) -> (u32, BlockNumber) {
+            (self.creation_limit, self.creation_period)
+        }
+
+        /// Lets each account create at most `limit` pools every `period` blocks;
+        /// a limit of 0 turns it off.
+        #[ink(message)]
+        pub fn set_creation_limit(&mut self, limit: u32, period: BlockNumber) {
+            assert!(limit == 0 || period > 0, "ERR_CREATION_PERIOD");
+            if !self._admin(b"set_creation_limit", (limit, period)) {
+                return;
+            }
+            self.creation_limit = limit;
+            self.creation_period = period;
+            self.env().emit_event(LogCreationLimit {
+                limit,
+                period,
+            });
+        }
+
+        /// Flags a spam pool: `is_pool` turns false and it leaves the token index.
+        /// The pool itself and its funds are left untouched.
+        #[ink(message)]
+        pub fn deregister_pool(&mut self, pool_address: AccountId) {
+            if !self._admin(b"deregister_pool", pool_address) {
+                return;
+            }
+            assert!(self.is_pool(pool_address), "ERR_NOT_POOL");
+            self.deregistered.insert(pool_address, true);
+
+            let p: Pool = FromAccountId::from_account_id(pool_address);
+            for t in p.get_current_tokens() {
+                self._remove_token_pool(t, pool_address);
+            }
+
+            self.env().emit_event(LogDeregisterPool {
+                caller: Some(Self::env().caller()),
+                pool: Some(pool_address),
+            });
+        }
+
+        // Contracts instantiated by this factory get the address
+        // blake2_256(factory ++ code_hash ++ salt), salt being `salt.to_le_bytes()`.
+        fn _predict_address(&self, code_hash: Hash, salt: u32) -> AccountId {
+            // fixed-size values SCALE-encode to their raw bytes, so this is the plain concatenation
+            let buf = (self.env().account_id(), code_hash, salt.to_le_bytes());
+            let mut output = [0u8; 32];
+            ink_env::hash_encoded::<Blake2x256, _>(&buf, &mut output);
+            return AccountId::from(output);
+        }
+
+        #[ink(message)]
+        pub fn predict_pool_address(&self, salt: u32) -> AccountId {
+            return self._predict_address(self.pool_code_hash, salt);
+        }
+
+        #[ink(message)]
+        pub fn predict_token_address(&self, salt: u32) -> AccountId {
+            return self._predict_address(self.token_code_hash, salt);
+        }
+
+        #[ink(message)]
+        pub fn is_salt_used(&self, salt: u32) -> bool {
+            return self.used_salts.get(&salt).copied().unwrap_or(false);
+        }
+
+        #[ink(message)]
+        pub fn get_pool_count(&self) -> u128 {
+            return self.pools.len().into();
+        }
+
+        /// Pools in creation order, `limit` at most, starting at `offset`.
+        #[ink(message)]
+        pub fn get_pools(&self, offset: u32, limit: u32) -> Vec<AccountId> {
+            return self.pools.iter().skip(offset as usize).take(limit as usize).copied().collect();
+        }
+
+        #[ink(message)]
+        pub fn get_token_pool_count(&self, token: AccountId) -> u32 {
+            return self.token_pool_count.get(&token).copied().unwrap_or(0);
+        }
+
+        /// Registered pools holding `token`, `limit` at most, starting at `offset`.
+        /// The order changes as pools leave the index.
+        #[ink(message)]
+        pub fn pools_for_token(&self, token: AccountId, offset: u32, limit: u32) -> Vec<AccountId> {
+            let end = self.get_token_pool_count(token).min(offset.saturating_add(limit));
+            return (offset..end)
+                .filter_map(|i| self.token_pool_at.get(&(token, i)).copied())
+                .collect();
+        }
+
+        fn _is_token_pool(&self, token: AccountId, pool: AccountId) -> bool {
+            return self.token_pool_index.get(&(token, pool)).is_some();
+        }
+
+        #[ink(message)]
+        pub fn pools_for_pair(&self, a: AccountId, b: AccountId) -> Vec<AccountId> {
+            // walk the token with fewer pools and look the other one up
+            let (walk, other) = if self.get_token_pool_count(a) <= self.get_token_pool_count(b) {
+                (a, b)
+            } else {
+                (b, a)
+            };
+            return self.pools_for_token(walk, 0, self.get_token_pool_count(walk))
+                .into_iter()
+                .filter(|p| self._is_token_pool(other, *p))
+                .collect();
+        }
+
+        #[ink(message)]
+        pub fn pools_for_pair_and_tier(&self, a: AccountId, b: AccountId, tier: u32) -> Vec<AccountId> {
+            return self.pools_for_pair(a, b)
+                .into_iter()
+                .filter(|p| self.get_pool_tier(*p) == Some(tier))
+                .collect();
+        }
+
+        /// Called by a registered pool when it binds `token`.
+        #[ink(message)]
+        pub fn pool_token_bound(&mut self, token: AccountId) {
+            let pool = Self::env().caller();
+            assert!(self._is_created_pool(pool), "ERR_NOT_POOL");
+            // deregistered pools keep working but stay out of the index
+            if !self.is_deregistered(pool) {
+                self._add_token_pool(token, pool);
+            }
+        }
+
+        fn _add_token_pool(&mut self, token: AccountId, pool: AccountId) {
+            if self._is_token_pool(token, pool) {
+                return;
+            }
+            let count = self.get_token_pool_count(token);
+            self.token_pool_at.insert((token, count), pool);
+            self.token_pool_index.insert((token, pool), count);
+            self.token_pool_count.insert(token, count + 1);
+        }
+
+        // moves the last pool of `token` into the removed pool's slot
+        fn _remove_token_pool(&mut self, token: AccountId, pool: AccountId) {
+            let index = match self.token_pool_index.take(&(token, pool)) {
+                Some(index) => index,
+                None => return,
+            };
+            let last = self.get_token_pool_count(token) - 1;
+            if index != last {
+                let moved = self.token_pool_at.get(&(token, last)).copied().unwrap();
+                self.token_pool_at.insert((token, index), moved);
+                self.token_pool_index.insert((token, moved), index);
+            }
+            self.token_pool_at.take(&(token, last));
+            self.token_pool_count.insert(token, last);
+        }
+
+        /// Called by a registered pool when it unbinds `token`.
+        #[ink(message)]
+        pub fn pool_token_unbound(&mut self, token: AccountId) {
+            let pool = Self::env().caller();
+            assert!(self._is_created_pool(pool), "ERR_NOT_POOL");
+            self._remove_token_pool(token, pool);
+        }
+
+        #[ink(message)]
+        pub fn get_current_version(&self) -> u32 {
+            return self.code_versions.len() - 1;
+        }
+
+        #[ink(message)]
+        pub fn get_code_version(&self, version: u32) -> Option<CodeVersion> {
+            return self.code_versions.get(version).cloned();
+        }
+
+        #[ink(message)]
+        pub fn get_pool_version(&self, pool: AccountId) -> Option<u32> {
+            return self.pool_versions.get(&pool).copied();
+        }
+
+        /// Registers new token and pool code; new pools are created with it.
+        #[ink(message)]
+        pub fn add_code_version(&mut self,
+                                token_code_hash: Hash,
+                                pool_code_hash: Hash,
+                                storage_version: u32) -> u32 {
+            // until enough signers approved, the version in use is returned
+            if !self._admin(b"add_code_version", (token_code_hash, pool_code_hash, storage_version)) {
+                return self.get_current_version();
+            }
+            assert_ne!(token_code_hash, Hash::from([0; 32]));
+            assert_ne!(pool_code_hash, Hash::from([0; 32]));
+            let current = self.code_versions.get(self.get_current_version()).unwrap().storage_version;
+            assert!(storage_version >= current, "ERR_STORAGE_VERSION");
+
+            self.token_code_hash = token_code_hash;
+            self.pool_code_hash = pool_code_hash;
+            self.code_versions.push(CodeVersion {
+                token_code_hash,
+                pool_code_hash,
+                storage_version,
+            });
+
+            let version = self.get_current_version();
+            self.env().emit_event(LogCodeVersion {
+                version,
+                token_code_hash,
+                pool_code_hash,
+                storage_version,
+            });
+            return version;
+        }
+
+        #[ink(message)]
+        pub fn get_pending_upgrade(&self, pool: AccountId) -> Option<(u32, BlockNumber)> {
+            return self.pending_upgrades.get(&pool).copied();
+        }
+
+        /// Announces that `pool_address` will be upgraded to `version`; `upgrade_pool` is
+        /// allowed `UPGRADE_DELAY` blocks later. Announcing again restarts the delay.
+        #[ink(message)]
+        pub fn announce_upgrade(&mut self, pool_address: AccountId, version: u32) {
+            if !self._admin(b"announce_upgrade", (pool_address, version)) {
+                return;
+            }
+            assert!(self.is_pool(pool_address), "ERR_NOT_POOL");
+            let from = self.get_pool_version(pool_address).unwrap_or(0);
+            assert!(version > from, "ERR_OLD_VERSION");
+            assert!(self.get_code_version(version).is_some(), "ERR_NO_VERSION");
+
+            let block = self.env().block_number();
+            self.pending_upgrades.insert(pool_address, (version, block));
+            self.env().emit_event(LogAnnounceUpgrade {
+                pool: Some(pool_address),
+                version,
+                executable_at: block + UPGRADE_DELAY,
+            });
+        }
+
+        /// Migrates an existing pool to the pool code of a newer `version`, announced with
+        /// `announce_upgrade` at least `UPGRADE_DELAY` blocks before. A new pool is
+        /// instantiated from that code with `salt`, takes over the old pool's LP token,
+        /// balances and settings, and replaces it in the registry; LPs keep their shares.
+        /// Returns the new pool, or `None` while the upgrade still awaits approvals.
+        #[ink(message)]
+        pub fn upgrade_pool(&mut self,
+                            pool_address: AccountId,
+                            version: u32,
+                            salt: u32,
+                            pool_endowment: u128) -> Option<AccountId> {
+            if !self._admin(b"upgrade_pool", (pool_address, version, salt, pool_endowment)) {
+                return None;
+            }
+            assert!(self.is_pool(pool_address), "ERR_NOT_POOL");
+            let from = self.get_pool_version(pool_address).unwrap_or(0);
+            assert!(version > from, "ERR_OLD_VERSION");
+            let v = self.get_code_version(version).expect("ERR_NO_VERSION");
+            let (announced, at) = self.get_pending_upgrade(pool_address).expect("ERR_NOT_ANNOUNCED");
+            assert!(announced == version, "ERR_NOT_ANNOUNCED");
+            assert!(self.env().block_number() >= at + UPGRADE_DELAY, "ERR_UPGRADE_DELAY");
+            assert!(!self.is_salt_used(salt), "ERR_SALT_USED");
+            self.used_salts.insert(salt, true);
+            self.pending_upgrades.take(&pool_address);
+
+            let mut old: Pool = FromAccountId::from_account_id(pool_address);
+            let tokens = old.get_current_tokens();
+            let weights: Vec<u128> = tokens.iter().map(|t| old.get_denormalized_weight(*t)).collect();
+            let balances: Vec<u128> = tokens.iter().map(|t| old.get_balance(*t)).collect();
+            // read before `migrate_to` clears them
+            let public_swap = old.is_public_swap();
+            let finalized = old.is_finalized();
+
+            let pool_params = Pool::new(self.math_address, self.base_address, old.get_token(), old.get_config())
+                .endowment(pool_endowment)
+                .code_hash(v.pool_code_hash)
+                .salt_bytes(salt.to_le_bytes())
+                .params();
+            let new_address = self
+                .env()
+                .instantiate_contract(&pool_params)
+                .expect("failed at instantiating the `pool` contract");
+
+            let mut p: Pool = FromAccountId::from_account_id(new_address);
+            // the registered storage version must be the one the code really has
+            assert!(p.get_storage_version() == v.storage_version, "ERR_STORAGE_VERSION");
+
+            old.migrate_to(new_address);
+            p.import_state(old.get_controller(),
+                           old.get_swap_fee(),
+                           old.is_dynamic_fee(),
+                           old.get_referral_share(),
+                           public_swap,
+                           finalized,
+                           old.get_referrers(),
+                           tokens.clone(),
+                           weights,
+                           balances);
+            p.set_registered();
+
+            self.is_pool.insert(new_address, true);
+            self.pools.push(new_address);
+            self.pool_versions.insert(new_address, version);
+            if let Some(tier) = self.pool_tiers.get(&pool_address).copied() {
+                self.pool_tiers.insert(new_address, tier);
+                p.lock_swap_fee();
+            }
+            self.deregistered.insert(pool_address, true);
+            for t in tokens.iter() {
+                self._remove_token_pool(*t, pool_address);
+                self._add_token_pool(*t, new_address);
+            }
+
+            self.env().emit_event(LogUpgradePool {
+                pool: Some(pool_address),
+                new_pool: Some(new_address),
+                version,
+            });
+            return Some(new_address);
+        }
+
+        // Authorizes an admin action identified by its name and arguments.
+        // With M-of-N mode off only `labs` may call it and it runs at once;
+        // otherwise each signer's call is recorded as an approval and the
+        // action runs on the call that reaches the threshold.
+        fn _admin<T: scale::Encode>(&mut self, name: &[u8], args: T) -> bool {
+            let sender = Self::env().caller();
+            if self.admin_threshold == 0 {
+                assert!(sender == self.labs, "ERR_NOT_CONVLABS");
+                return true;
+            }
+
+            assert!(self.admin_signers.contains(&sender), "ERR_NOT_SIGNER");
+            let mut output = [0u8; 32];
+            ink_env::hash_encoded::<Blake2x256, _>(&(name, args, self.admin_nonce), &mut output);
+            let action = Hash::from(output);
+
+            let mut approvals = self.approvals.get(&action).cloned().unwrap_or_default();
+            if !approvals.contains(&sender) {
+                approvals.push(sender);
+            }
+            let count = approvals.len() as u32;
+            self.env().emit_event(LogAdminApproval {
+                signer: Some(sender),
+                action,
+                approvals: count,
+            });
+
+            if count < self.admin_threshold {
+                self.approvals.insert(action, approvals);
+                return false;
+            }
+            self.approvals.take(&action);
+            self.env().emit_event(LogAdminExecuted {
+                caller: Some(sender),
+                action,
+            });
+            return true;
+        }
+
         #[ink(message)]
         pub fn get_labs(&self) -> AccountId {
             self.labs
         }
 
+        #[ink(message)]
+        pub fn get_pending_labs(&self) -> Option<AccountId> {
+            self.pending_labs
+        }
+
+        #[ink(message)]
+        pub fn get_admin_signers(&self) -> (Vec<AccountId>, u32) {
+            (self.admin_signers.clone(), self.admin_threshold)
+        }
+
+        /// Sets `threshold`-of-`signers` approval for admin messages; a threshold of 0
+        /// turns it off and gives `labs` sole control again. Pending approvals are dropped.
+        #[ink(message)]
+        pub fn set_admin_signers(&mut self, signers: Vec<AccountId>, threshold: u32) {
+            if !self._admin(b"set_admin_signers", (&signers, threshold)) {
+                return;
+            }
+            assert!(threshold as usize <= signers.len(), "ERR_THRESHOLD");
+            for (i, a) in signers.iter().enumerate() {
+                assert!(!signers[i + 1..].contains(a), "ERR_DUPLICATE_SIGNER");
+            }
+
+            self.env().emit_event(LogAdminSigners {
+                caller: Some(Self::env().caller()),
+                signers: signers.clone(),
+                threshold,
+            });
+            self.admin_signers = signers;
+            self.admin_threshold = threshold;
+            self.admin_nonce += 1;
+        }
+
+        /// Changes `labs` immediately; prefer `propose_labs` and `accept_labs`.
         #[ink(message)]
         pub fn set_labs(&mut self, b: AccountId) {
+            if !self._admin(b"set_labs", b) {
+                return;
+            }
             let sender = Self::env().caller();
-            assert!(sender == self.labs, "ERR_NOT_CONVLABS");
             self.env().emit_event(LogLabs {
                 caller: Some(sender),
                 labs: Some(b),
             });
 
             self.labs = b;
+            self.pending_labs = None;
+        }
+
+        /// First step of handing `labs` over; `b` must call `accept_labs`.
+        #[ink(message)]
+        pub fn propose_labs(&mut self, b: AccountId) {
+            if !self._admin(b"propose_labs", b) {
+                return;
+            }
+            self.env().emit_event(LogLabsProposed {
+                caller: Some(Self::env().caller()),
+                labs: Some(b),
+            });
+            self.pending_labs = Some(b);
+        }
+
+        #[ink(message)]
+        pub fn accept_labs(&mut self) {
+            let sender = Self::env().caller();
+            assert!(self.pending_labs == Some(sender), "ERR_NOT_PENDING_LABS");
+            self.env().emit_event(LogLabs {
+                caller: Some(sender),
+                labs: Some(sender),
+            });
+
+            self.labs = sender;
+            self.pending_labs = None;
+        }
+
+        #[ink(message)]
+        pub fn cancel_labs_transfer(&mut self) {
+            if !self._admin(b"cancel_labs_transfer", ()) {
+                return;
+            }
+            let pending = self.pending_labs.take();
+            assert!(pending.is_some(), "ERR_NO_PENDING_LABS");
+            self.env().emit_event(LogLabsCancelled {
+                caller: Some(Self::env().caller()),
+                labs: pending,
+            });
+        }
+
+        #[ink(message)]
+        pub fn get_treasury(&self) -> AccountId {
+            self.treasury.unwrap_or(self.labs)
+        }
+
+        #[ink(message)]
+        pub fn is_collect_exit(&self) -> bool {
+            self.collect_exit
+        }
+
+        /// Sets where collected fees go; `None` sends them to `labs`.
+        #[ink(message)]
+        pub fn set_treasury(&mut self, treasury: Option<AccountId>) {
+            if !self._admin(b"set_treasury", treasury) {
+                return;
+            }
+            self.treasury = treasury;
+        }
+
+        /// When set, collected pool shares are exited into the underlying tokens
+        /// before being forwarded to the treasury.
+        #[ink(message)]
+        pub fn set_collect_exit(&mut self, exit: bool) {
+            if !self._admin(b"set_collect_exit", exit) {
+                return;
+            }
+            self.collect_exit = exit;
         }
 
         #[ink(message)]
         pub fn collect(&mut self, pool_address: AccountId) {
-            assert!(Self::env().caller() == self.labs, "ERR_NOT_CONVLABS");
+            if !self._admin(b"collect", pool_address) {
+                return;
+            }
+            assert!(self.is_pool(pool_address), "ERR_NOT_POOL");
+            assert!(self._collect(pool_address), "ERR_COLLECT_FAILED");
+        }
+
+        /// Like `collect` for each of `pools`; pools that are not registered or
+        /// fail to pay out are skipped.
+        #[ink(message)]
+        pub fn collect_many(&mut self, pools: Vec<AccountId>) {
+            if !self._admin(b"collect_many", &pools) {
+                return;
+            }
+            for pool_address in pools {
+                self._collect(pool_address);
+            }
+        }
+
+        /// Collects from the registered pools `get_pools(offset, limit)` returns;
+        /// deregistered pools and pools that fail to pay out are skipped.
+        #[ink(message)]
+        pub fn collect_all(&mut self, offset: u32, limit: u32) {
+            if !self._admin(b"collect_all", (offset, limit)) {
+                return;
+            }
+            for pool_address in self.get_pools(offset, limit) {
+                self._collect(pool_address);
+            }
+        }
+
+        // Returns false, leaving the shares in place, when `pool_address` is not registered
+        // (a migrated pool no longer controls its token) or the exit or a transfer fails.
+        fn _collect(&mut self, pool_address: AccountId) -> bool {
+            if !self.is_pool(pool_address) {
+                return false;
+            }
             let this = self.env().account_id();
+            let treasury = self.get_treasury();
             let mut p: Pool = FromAccountId::from_account_id(pool_address);
-            let collected = p.balance_of(this);
-            let r = p.transfer(self.labs, collected);
-            assert!(r, "ERR_TOKEN_FAILED");
+            let mut t: Token = FromAccountId::from_account_id(p.get_token());
+            let collected = t.balance_of(this);
+
+            let mut tokens: Vec<AccountId> = Vec::new();
+            let mut amounts: Vec<u128> = Vec::new();
+            if collected == 0 {
+                // nothing to sweep
+            } else if self.collect_exit {
+                tokens = p.get_final_tokens();
+                let before: Vec<u128> = tokens.iter()
+                    .map(|token| PAT::from_account_id(*token).balance_of(this))
+                    .collect();
+                if p.call_mut().exit_pool(collected, vec![0; tokens.len()]).fire().is_err() {
+                    return false;
+                }
+
+                for (i, token) in tokens.iter().enumerate() {
+                    let mut erc: PAT = FromAccountId::from_account_id(*token);
+                    let amount = erc.balance_of(this) - before[i];
+                    // a token that fails to transfer is left with this factory and reported as 0
+                    if amount > 0 && !matches!(erc.call_mut().transfer(treasury, amount).fire(), Ok(Ok(()))) {
+                        amounts.push(0);
+                        continue;
+                    }
+                    amounts.push(amount);
+                }
+            } else if !matches!(t.call_mut().transfer(treasury, collected).fire(), Ok(Ok(()))) {
+                return false;
+            }
+
+            self.env().emit_event(LogCollect {
+                pool: Some(pool_address),
+                treasury: Some(treasury),
+                shares: collected,
+                tokens,
+                amounts,
+            });
+            return true;
         }
     }
 }
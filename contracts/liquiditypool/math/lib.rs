@@ -15,6 +15,14 @@ pub use self::math::MIN_BALANCE;
 pub use self::math::MAX_TOTAL_WEIGHT;
 pub use self::math::MAX_OUT_RATIO;
 pub use self::math::MAX_IN_RATIO;
+pub use self::math::MAX_REFERRAL_SHARE;
+pub use self::math::VOLATILITY_DECAY;
+pub use self::math::VOLATILITY_FEE_FACTOR;
+pub use self::math::VOLATILITY_HALF_LIFE;
+pub use self::math::HARD_MAX_BOUND_TOKENS;
+pub use self::math::HARD_MAX_TOTAL_WEIGHT;
+pub use self::math::MAX_MANAGED_RATIO;
+pub use self::math::MAX_MANAGED_REPORT_CHANGE;
 
 use ink_lang as ink;
 
@@ -41,6 +49,24 @@ mod math {
     pub const MAX_IN_RATIO: u128      = BONE / 2;
     pub const MAX_OUT_RATIO: u128     = (BONE / 3) + 1;
 
+    pub const MAX_REFERRAL_SHARE: u128 = BONE / 2;
+
+    // weight of the newest sample in the swap volatility average,
+    // and how much of that volatility is added on top of the swap fee
+    pub const VOLATILITY_DECAY: u128      = BONE / 10;
+    pub const VOLATILITY_FEE_FACTOR: u128 = BONE / 2;
+    // blocks without swaps after which the volatility has halved
+    pub const VOLATILITY_HALF_LIFE: u128  = 100;
+
+    // factory-wide hard limits for per-pool configs
+    pub const HARD_MAX_BOUND_TOKENS: u128 = 16;
+    pub const HARD_MAX_TOTAL_WEIGHT: u128 = BONE * 100;
+
+    // most of a token balance an asset manager may hold, and how far a single
+    // report may move the managed amount relative to its previous value
+    pub const MAX_MANAGED_RATIO: u128         = BONE / 2;
+    pub const MAX_MANAGED_REPORT_CHANGE: u128 = BONE / 10;
+
     #[ink(storage)]
     pub struct Math {
     }
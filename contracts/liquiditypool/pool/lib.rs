@@ -1,11 +1,12 @@
 #![cfg_attr(not(feature = "std"), no_std)]
 
-pub use self::pool::Pool;
+pub use self::pool::{Pool, PoolConfig, SwapStep, STORAGE_VERSION};
 use ink_lang as ink;
 
 #[ink::contract]
 mod pool {
     use ink_prelude::{
+        string::String,
         vec::Vec,
     };
     use ink_storage::{
@@ -20,6 +21,7 @@ mod pool {
 
     use math::Math;
     use math::{
+        BONE,
         EXIT_FEE,
         MIN_FEE,
         MAX_FEE,
@@ -32,14 +34,30 @@ mod pool {
         MAX_TOTAL_WEIGHT,
         MAX_OUT_RATIO,
         MAX_IN_RATIO,
+        MAX_REFERRAL_SHARE,
+        VOLATILITY_DECAY,
+        VOLATILITY_FEE_FACTOR,
+        VOLATILITY_HALF_LIFE,
+        HARD_MAX_BOUND_TOKENS,
+        HARD_MAX_TOTAL_WEIGHT,
+        MAX_MANAGED_RATIO,
+        MAX_MANAGED_REPORT_CHANGE,
     };
     use base::Base;
     use token::Token;
+    use factoryproxy::FactoryInterface;
     use cdot::PAT;
 
     use ink_env::call::FromAccountId;
+    use ink_lang::ToAccountId;
     use core::convert::TryInto;
 
+    /// Storage layout version of this pool code; bumped whenever `Pool`'s storage changes.
+    pub const STORAGE_VERSION: u32 = 1;
+
+    /// Blocks an asset manager has to wait between two `report_managed_balance` calls.
+    pub const MANAGED_REPORT_INTERVAL: BlockNumber = 600;
+
     #[derive(
     Debug, PartialEq, Eq, Clone, scale::Encode, scale::Decode, SpreadLayout, PackedLayout,
     )]
@@ -54,17 +72,106 @@ mod pool {
         pub balance: u128,
     }
 
+    /// One exact-in step of `Pool::batch_swap`.
+    #[derive(
+    Debug, PartialEq, Eq, Clone, scale::Encode, scale::Decode, SpreadLayout, PackedLayout,
+    )]
+    #[cfg_attr(
+    feature = "std",
+    derive(scale_info::TypeInfo, ink_storage::traits::StorageLayout)
+    )]
+    pub struct SwapStep {
+        pub token_in: AccountId,
+        pub token_out: AccountId,
+        pub token_amount_in: u128,   // 0 uses the output of the previous step
+    }
+
+    /// Per-pool limits, fixed when the pool is created.
+    #[derive(
+    Debug, PartialEq, Eq, Clone, scale::Encode, scale::Decode, SpreadLayout, PackedLayout,
+    )]
+    #[cfg_attr(
+    feature = "std",
+    derive(scale_info::TypeInfo, ink_storage::traits::StorageLayout)
+    )]
+    pub struct PoolConfig {
+        pub min_bound_tokens: u128,
+        pub max_bound_tokens: u128,
+        pub min_weight: u128,
+        pub max_weight: u128,
+        pub max_total_weight: u128,
+        pub min_balance: u128,
+        pub max_in_ratio: u128,
+        pub max_out_ratio: u128,
+    }
+
+    impl Default for PoolConfig {
+        fn default() -> Self {
+            Self {
+                min_bound_tokens: MIN_BOUND_TOKENS,
+                max_bound_tokens: MAX_BOUND_TOKENS,
+                min_weight: MIN_WEIGHT,
+                max_weight: MAX_WEIGHT,
+                max_total_weight: MAX_TOTAL_WEIGHT,
+                min_balance: MIN_BALANCE,
+                max_in_ratio: MAX_IN_RATIO,
+                max_out_ratio: MAX_OUT_RATIO,
+            }
+        }
+    }
+
+    impl PoolConfig {
+        /// Checks the config against the factory-wide hard limits.
+        pub fn validate(&self) {
+            assert!(self.min_bound_tokens >= MIN_BOUND_TOKENS, "ERR_MIN_TOKENS");
+            assert!(self.max_bound_tokens >= self.min_bound_tokens, "ERR_MAX_TOKENS");
+            assert!(self.max_bound_tokens <= HARD_MAX_BOUND_TOKENS, "ERR_MAX_TOKENS");
+            assert!(self.min_weight >= MIN_WEIGHT, "ERR_MIN_WEIGHT");
+            assert!(self.max_weight >= self.min_weight, "ERR_MAX_WEIGHT");
+            assert!(self.max_weight <= MAX_WEIGHT, "ERR_MAX_WEIGHT");
+            assert!(self.max_total_weight >= self.max_weight, "ERR_MAX_TOTAL_WEIGHT");
+            assert!(self.max_total_weight <= HARD_MAX_TOTAL_WEIGHT, "ERR_MAX_TOTAL_WEIGHT");
+            assert!(self.min_balance >= MIN_BALANCE, "ERR_MIN_BALANCE");
+            assert!(self.max_in_ratio != 0 && self.max_in_ratio <= MAX_IN_RATIO, "ERR_MAX_IN_RATIO");
+            assert!(self.max_out_ratio != 0 && self.max_out_ratio <= MAX_OUT_RATIO, "ERR_MAX_OUT_RATIO");
+        }
+    }
+
+    /// Asset manager allowed to lend out part of a token's pool balance.
+    #[derive(
+    Debug, PartialEq, Eq, Clone, scale::Encode, scale::Decode, SpreadLayout, PackedLayout,
+    )]
+    #[cfg_attr(
+    feature = "std",
+    derive(scale_info::TypeInfo, ink_storage::traits::StorageLayout)
+    )]
+    pub struct AssetManager {
+        pub manager: AccountId,
+        pub max_ratio: u128,   // max fraction of `Record::balance` that may be managed
+        pub managed: u128,     // part of `Record::balance` currently held by the manager
+        pub last_report: BlockNumber,   // block of the last `report_managed_balance`, 0 if none
+    }
+
     #[ink(storage)]
     pub struct Pool {
         mutex: bool,
         factory: AccountId,
+        registered: bool,
         controller: AccountId,
         public_swap: bool,
         swap_fee: u128,
+        dynamic_fee: bool,
+        fee_locked: bool,   // set by the factory for pools created with a fee tier
+        volatility: u128,
+        volatility_block: BlockNumber,   // block `volatility` was last updated in
+        referral_share: u128,
+        referrers: StorageHashMap<AccountId, bool>,
         finalized: bool,
         tokens: StorageVec<AccountId>,
         records: StorageHashMap<AccountId, Record>,
+        managers: StorageHashMap<AccountId, AssetManager>,
         total_weight: u128,
+        config: PoolConfig,
 
         base: Lazy<Base>,
         math: Lazy<Math>,
@@ -81,6 +188,25 @@ mod pool {
         token_out: Option<AccountId>,
         token_amount_in: u128,
         token_amount_out: u128,
+        swap_fee: u128,
+    }
+
+    #[ink(event)]
+    pub struct LogReferral {
+        #[ink(topic)]
+        referrer: Option<AccountId>,
+        #[ink(topic)]
+        caller: Option<AccountId>,
+        #[ink(topic)]
+        token_in: Option<AccountId>,
+        fee_amount: u128,
+    }
+
+    #[ink(event)]
+    pub struct LogReferrer {
+        #[ink(topic)]
+        referrer: Option<AccountId>,
+        allowed: bool,
     }
 
     #[ink(event)]
@@ -101,6 +227,31 @@ mod pool {
         token_amount_out: u128,
     }
 
+    #[ink(event)]
+    pub struct LogAssetManager {
+        #[ink(topic)]
+        token: Option<AccountId>,
+        #[ink(topic)]
+        manager: Option<AccountId>,
+        max_ratio: u128,
+    }
+
+    #[ink(event)]
+    pub struct LogManagedBalance {
+        #[ink(topic)]
+        token: Option<AccountId>,
+        #[ink(topic)]
+        manager: Option<AccountId>,
+        managed: u128,
+        balance: u128,
+    }
+
+    #[ink(event)]
+    pub struct LogMigrate {
+        #[ink(topic)]
+        to: Option<AccountId>,
+    }
+
     #[ink(event, anonymous)]
     pub struct LogCall {
         #[ink(topic)]
@@ -114,7 +265,9 @@ mod pool {
         #[ink(constructor)]
         pub fn new(math_address: AccountId,
                    base_address: AccountId,
-                   token_address:  AccountId) -> Self {
+                   token_address:  AccountId,
+                   config: PoolConfig) -> Self {
+            config.validate();
             let caller = Self::env().caller();
 
             let base: Base = FromAccountId::from_account_id(base_address);
@@ -124,13 +277,22 @@ mod pool {
             let instance = Self {
                 mutex: false,
                 factory: caller,
+                registered: false,
                 controller: caller,
                 public_swap: false,
                 swap_fee: MIN_FEE,
+                dynamic_fee: false,
+                fee_locked: false,
+                volatility: 0,
+                volatility_block: 0,
+                referral_share: 0,
+                referrers: StorageHashMap::new(),
                 finalized: false,
                 tokens: StorageVec::new(),
                 records: StorageHashMap::new(),
+                managers: StorageHashMap::new(),
                 total_weight: 0,
+                config,
 
                 base: Lazy::new(base),
                 math: Lazy::new(math),
@@ -230,9 +392,10 @@ mod pool {
             return self.token.balance_of(owner);
         }
 
+        /// Address of this pool's LP share token.
         #[ink(message)]
-        pub fn transfer(&mut self, to: AccountId, value: u128) -> bool {
-            return self.token.transfer(to, value);
+        pub fn get_token(&self) -> AccountId {
+            return self.token.to_account_id();
         }
 
         #[ink(message)]
@@ -299,10 +462,67 @@ mod pool {
             return self._get_record(token).unwrap().balance;
         }
 
+        #[ink(message)]
+        pub fn get_config(&self) -> PoolConfig {
+            self._view_lock_();
+            return self.config.clone();
+        }
+
+        // In dynamic-fee mode `swap_fee` is the floor, and the recent
+        // volatility is added on top of it, clamped to [MIN_FEE, MAX_FEE]
+        fn _effective_swap_fee(&self) -> u128 {
+            if !self.dynamic_fee {
+                return self.swap_fee;
+            }
+
+            let fee = self.math.badd(self.swap_fee, self.math.bmul(self._current_volatility(), VOLATILITY_FEE_FACTOR));
+            if fee > MAX_FEE {
+                return MAX_FEE;
+            }
+            if fee < MIN_FEE {
+                return MIN_FEE;
+            }
+            return fee;
+        }
+
+        // the stored volatility, halved for every VOLATILITY_HALF_LIFE blocks
+        // without swaps, so the fee comes back down in quiet periods
+        fn _current_volatility(&self) -> u128 {
+            let elapsed = u128::from(self.env().block_number() - self.volatility_block);
+            let halvings = elapsed / VOLATILITY_HALF_LIFE;
+            if halvings >= 128 {
+                return 0;
+            }
+            return self.volatility >> halvings;
+        }
+
+        fn _update_volatility(&mut self, spot_price_before: u128, spot_price_after: u128) {
+            if !self.dynamic_fee {
+                return;
+            }
+
+            let (diff, _) = self.math.bsub_sign(spot_price_after, spot_price_before);
+            let change = self.math.bdiv(diff, spot_price_before);
+            let kept = self.math.bmul(self._current_volatility(), self.math.bsub(BONE, VOLATILITY_DECAY));
+            self.volatility = self.math.badd(kept, self.math.bmul(change, VOLATILITY_DECAY));
+            self.volatility_block = self.env().block_number();
+        }
+
         #[ink(message)]
         pub fn get_swap_fee(&self) -> u128 {
             self._view_lock_();
-            return self.swap_fee;
+            return self._effective_swap_fee();
+        }
+
+        #[ink(message)]
+        pub fn is_dynamic_fee(&self) -> bool {
+            return self.dynamic_fee;
+        }
+
+        #[ink(message)]
+        pub fn get_volatility(&self) -> u128 {
+            self._view_lock_();
+            return self._current_volatility();
         }
 
         #[ink(message)]
@@ -316,6 +536,7 @@ mod pool {
             self._view_lock_();
 
             assert!(!self.finalized, "ERR_IS_FINALIZED");
+            assert!(!self.fee_locked, "ERR_FEE_LOCKED");
             assert_eq!(self.controller, self._get_sender(), "ERR_NOT_CONTROLLER");
             assert!(fee >= MIN_FEE, "ERR_MIN_FEE");
             assert!(fee <= MAX_FEE, "ERR_MAX_FEE");
@@ -323,6 +544,91 @@ mod pool {
             self.swap_fee = fee;
         }
 
+        #[ink(message)]
+        pub fn set_dynamic_fee(&mut self, dynamic: bool) {
+            self._view_lock_();
+
+            assert!(!self.finalized, "ERR_IS_FINALIZED");
+            assert!(!self.fee_locked, "ERR_FEE_LOCKED");
+            assert_eq!(self.controller, self._get_sender(), "ERR_NOT_CONTROLLER");
+
+            self.dynamic_fee = dynamic;
+            self.volatility = 0;
+            self.volatility_block = self.env().block_number();
+        }
+
+        #[ink(message)]
+        pub fn get_referral_share(&self) -> u128 {
+            self._view_lock_();
+            return self.referral_share;
+        }
+
+        /// Sets the fraction of the swap fee paid to the referrer of a swap;
+        /// fixed once the pool is finalized.
+        #[ink(message)]
+        pub fn set_referral_share(&mut self, share: u128) {
+            self._lock_();
+            assert_eq!(self.controller, self._get_sender(), "ERR_NOT_CONTROLLER");
+            assert!(!self.finalized, "ERR_IS_FINALIZED");
+            assert!(share <= MAX_REFERRAL_SHARE, "ERR_MAX_REFERRAL_SHARE");
+            self.referral_share = share;
+            self._unlock_();
+        }
+
+        #[ink(message)]
+        pub fn is_referrer(&self, referrer: AccountId) -> bool {
+            self._view_lock_();
+            return self.referrers.get(&referrer).copied().unwrap_or(false);
+        }
+
+        #[ink(message)]
+        pub fn get_referrers(&self) -> Vec<AccountId> {
+            self._view_lock_();
+            return self.referrers.keys().copied().collect();
+        }
+
+        /// Allows or disallows `referrer` to earn the referral share; only allowed referrers
+        /// are paid, and the list is fixed once the pool is finalized.
+        #[ink(message)]
+        pub fn set_referrer(&mut self, referrer: AccountId, allowed: bool) {
+            self._lock_();
+            assert_eq!(self.controller, self._get_sender(), "ERR_NOT_CONTROLLER");
+            assert!(!self.finalized, "ERR_IS_FINALIZED");
+            if allowed {
+                self.referrers.insert(referrer, true);
+            } else {
+                self.referrers.take(&referrer);
+            }
+            self.env().emit_event(LogReferrer {
+                referrer: Some(referrer),
+                allowed,
+            });
+            self._unlock_();
+        }
+
+        // referrers the controller has not allowed earn nothing, the swap is still done
+        fn _referral_fee(&self, referrer: Option<AccountId>, token_amount_in: u128, swap_fee: u128) -> u128 {
+            if referrer.is_none() || self.referral_share == 0 || !self.is_referrer(referrer.unwrap()) {
+                return 0;
+            }
+            assert!(referrer != Some(self._get_sender()), "ERR_SELF_REFERRAL");
+            let fee = self.math.bmul(token_amount_in, swap_fee);
+            return self.math.bmul(fee, self.referral_share);
+        }
+
+        fn _pay_referral(&mut self, referrer: Option<AccountId>, sender: AccountId, token_in: AccountId, fee_amount: u128) {
+            if fee_amount == 0 {
+                return;
+            }
+            self.env().emit_event(LogReferral {
+                referrer,
+                caller: Some(sender),
+                token_in: Some(token_in),
+                fee_amount,
+            });
+            self._push_underlying(token_in, referrer.unwrap(), fee_amount);
+        }
+
         #[ink(message)]
         pub fn set_controller(&mut self, manager:AccountId) {
             self._lock_();
@@ -347,14 +653,162 @@ mod pool {
             let sender = self._get_sender();
             assert!(self.controller == sender, "ERR_NOT_CONTROLLER");
             assert!(!self.finalized, "ERR_IS_FINALIZED");
-            assert!(u128::from(self.tokens.len()) >= MIN_BOUND_TOKENS, "ERR_MIN_TOKENS");
+            assert!(u128::from(self.tokens.len()) >= self.config.min_bound_tokens, "ERR_MIN_TOKENS");
             self.finalized = true;
             self.public_swap = true;
+            self._derive_token_metadata();
             self._mint_pool_share(INIT_POOL_SUPPLY);
             self._push_pool_share(sender, INIT_POOL_SUPPLY);
             self._unlock_();
         }
 
+        // e.g. "CPT-cDOT-cUSD" from the bound tokens' symbols; a name or symbol
+        // the LP token already has is kept
+        fn _derive_token_metadata(&mut self) {
+            let name = self.token.name();
+            let symbol = self.token.symbol();
+            if name.is_some() && symbol.is_some() {
+                return;
+            }
+            let mut joined = String::new();
+            for t in self.tokens.iter() {
+                let erc: PAT = FromAccountId::from_account_id(*t);
+                let symbol = erc.token_symbol().unwrap_or_else(|| String::from("?"));
+                if !joined.is_empty() {
+                    joined.push('-');
+                }
+                joined.push_str(&symbol);
+            }
+            let name = name.unwrap_or_else(|| ink_prelude::format!("Conversation Pool Token {}", joined));
+            let symbol = symbol.unwrap_or_else(|| ink_prelude::format!("CPT-{}", joined));
+            self.token.set_metadata(Some(name), Some(symbol));
+        }
+
+        /// Called by the factory that created this pool, so bound tokens are
+        /// reported to its registry from now on.
+        #[ink(message)]
+        pub fn set_registered(&mut self) {
+            assert!(self.factory == self._get_sender(), "ERR_NOT_FACTORY");
+            self.registered = true;
+        }
+
+        #[ink(message)]
+        pub fn is_swap_fee_locked(&self) -> bool {
+            self._view_lock_();
+            return self.fee_locked;
+        }
+
+        /// Called by the factory once it set the fee of the pool's tier, so neither
+        /// `set_swap_fee` nor `set_dynamic_fee` can move away from it.
+        #[ink(message)]
+        pub fn lock_swap_fee(&mut self) {
+            assert!(self.factory == self._get_sender(), "ERR_NOT_FACTORY");
+            self.fee_locked = true;
+        }
+
+        #[ink(message)]
+        pub fn get_storage_version(&self) -> u32 {
+            return STORAGE_VERSION;
+        }
+
+        /// Called by the factory to hand this pool over to `new_pool`, a pool created
+        /// from newer code: all token balances and the LP token move there, and this
+        /// pool is left with no tokens bound. Managed funds must be returned first.
+        #[ink(message)]
+        pub fn migrate_to(&mut self, new_pool: AccountId) {
+            self._lock_();
+            assert!(self.factory == self._get_sender(), "ERR_NOT_FACTORY");
+            for t in self.tokens.iter() {
+                assert!(self._get_managed(*t) == 0, "ERR_MANAGED_BALANCE");
+            }
+
+            self.token.set_controller(new_pool);
+            while let Some(t) = self.tokens.pop() {
+                let balance = self._get_record(t).unwrap().balance;
+                let r = self._build_empty_record();
+                self.records.insert(t, r);
+                self._push_underlying(t, new_pool, balance);
+            }
+            self.total_weight = 0;
+            self.public_swap = false;
+            self.finalized = false;
+
+            self.env().emit_event(LogMigrate {
+                to: Some(new_pool),
+            });
+            self._unlock_();
+        }
+
+        /// Called by the factory on a pool it just created to take over the state of
+        /// the pool it replaces, whose balances were sent here by `migrate_to`.
+        #[ink(message)]
+        pub fn import_state(&mut self,
+                            controller: AccountId,
+                            swap_fee: u128,
+                            dynamic_fee: bool,
+                            referral_share: u128,
+                            public_swap: bool,
+                            finalized: bool,
+                            referrers: Vec<AccountId>,
+                            tokens: Vec<AccountId>,
+                            weights: Vec<u128>,
+                            balances: Vec<u128>) {
+            self._lock_();
+            let this = self.env().account_id();
+            assert!(self.factory == self._get_sender(), "ERR_NOT_FACTORY");
+            assert!(self.tokens.len() == 0 && !self.finalized, "ERR_NOT_EMPTY");
+            assert!(tokens.len() == weights.len() && tokens.len() == balances.len(), "ERR_LENGTH_MISMATCH");
+            assert!(tokens.len() as u128 <= self.config.max_bound_tokens, "ERR_MAX_TOKENS");
+
+            let mut total_weight = 0;
+            for i in 0..tokens.len() {
+                let erc: PAT = FromAccountId::from_account_id(tokens[i]);
+                assert!(erc.balance_of(this) >= balances[i], "ERR_INSUFFICIENT_CASH");
+                total_weight = self.math.badd(total_weight, weights[i]);
+                self.records.insert(tokens[i], Record {
+                    bound: true,
+                    index: self.tokens.len().into(),
+                    de_norm: weights[i],
+                    balance: balances[i],
+                });
+                self.tokens.push(tokens[i]);
+            }
+            assert!(total_weight <= self.config.max_total_weight, "ERR_MAX_TOTAL_WEIGHT");
+
+            self.total_weight = total_weight;
+            self.controller = controller;
+            self.swap_fee = swap_fee;
+            self.dynamic_fee = dynamic_fee;
+            self.referral_share = referral_share;
+            for r in referrers {
+                self.referrers.insert(r, true);
+            }
+            self.public_swap = public_swap;
+            self.finalized = finalized;
+            self._unlock_();
+        }
+
+        fn _notify_factory(&self, token: AccountId, bound: bool) {
+            if !self.registered {
+                return;
+            }
+            let mut f: FactoryInterface = FromAccountId::from_account_id(self.factory);
+            if bound {
+                f.pool_token_bound(token);
+            } else {
+                f.pool_token_unbound(token);
+            }
+        }
+
+        // tokens blocked in the factory's token registry cannot be bound to registered pools
+        fn _require_not_blocked(&self, token: AccountId) {
+            if !self.registered {
+                return;
+            }
+            let f: FactoryInterface = FromAccountId::from_account_id(self.factory);
+            assert!(!f.is_token_blocked(token), "ERR_TOKEN_BLOCKED");
+        }
+
         #[ink(message)]
         pub fn bind(&mut self, token: AccountId, balance: u128, denorm:u128) {
 
@@ -362,7 +816,8 @@ mod pool {
             assert!(self.controller == self._get_sender(), "ERR_NOT_CONTROLLER");
             assert!(!self._get_record(token).unwrap().bound, "ERR_IS_BOUND");
             assert!(!self.finalized, "ERR_IS_FINALIZED");
-            assert!(u128::from(self.tokens.len()) < MAX_BOUND_TOKENS, "ERR_MAX_TOKENS");
+            assert!(u128::from(self.tokens.len()) < self.config.max_bound_tokens, "ERR_MAX_TOKENS");
+            self._require_not_blocked(token);
             let r = Record {
                 bound: true,
                 index: self.tokens.len().into(),
@@ -371,6 +826,7 @@ mod pool {
             };
             self.records.insert(token, r);
             self.tokens.push(token);
+            self._notify_factory(token, true);
 
             debug_println!("ready to enter rebind()");
             self.rebind(token, balance, denorm);
@@ -399,9 +855,9 @@ mod pool {
 
             self._require_bound_finalized_controller(token);
 
-            assert!(denorm >= MIN_WEIGHT, "ERR_MIN_WEIGHT");
-            assert!(denorm <= MAX_WEIGHT, "ERR_MAX_WEIGHT");
-            assert!(balance >= MIN_BALANCE, "ERR_MIN_BALANCE");
+            assert!(denorm >= self.config.min_weight, "ERR_MIN_WEIGHT");
+            assert!(denorm <= self.config.max_weight, "ERR_MAX_WEIGHT");
+            assert!(balance >= self.config.min_balance, "ERR_MIN_BALANCE");
 
             debug_println!("ready to cal total_weight");
 
@@ -409,13 +865,15 @@ mod pool {
             let old_weight = self._get_record(token).unwrap().de_norm;
             if denorm > old_weight {
                 self.total_weight = self.math.badd(self.total_weight, self.math.bsub(denorm, old_weight));
-                assert!(self.total_weight <= MAX_TOTAL_WEIGHT, "ERR_MAX_TOTAL_WEIGHT");
+                assert!(self.total_weight <= self.config.max_total_weight, "ERR_MAX_TOTAL_WEIGHT");
             } else if denorm < old_weight {
                 self.total_weight = self.math.bsub(self.total_weight, self.math.bsub(old_weight, denorm));
             }
 
             // Adjust the balance record and actual token balance
             let old_balance = self._get_record(token).unwrap().balance;
+            // funds lent to an asset manager cannot be withdrawn
+            assert!(balance >= self._get_managed(token), "ERR_INSUFFICIENT_CASH");
 
             if let Some(record) = self.records.get_mut(&token) {
                 record.balance = balance;
@@ -441,6 +899,7 @@ mod pool {
             let sender = self._get_sender();
 
             self._require_bound_finalized_controller(token);
+            assert!(self._get_managed(token) == 0, "ERR_MANAGED_BALANCE");
 
             let token_balance = self._get_record(token).unwrap().balance;
             let token_exit_fee = self.math.bmul(token_balance, EXIT_FEE);
@@ -457,6 +916,7 @@ mod pool {
 
             let r = self._build_empty_record();
             self.records.insert(token, r);
+            self._notify_factory(token, false);
 
             self._push_underlying(token, sender, self.math.bsub(token_balance, token_exit_fee));
             self._push_underlying(token, self.factory, token_exit_fee);
@@ -471,11 +931,160 @@ mod pool {
 
             let erc: PAT = FromAccountId::from_account_id(token);
             let (_sender, this) = self._get_sender_and_this();
-            let balance = erc.balance_of(this);
+            let balance = self.math.badd(erc.balance_of(this), self._get_managed(token));
             self._update_balance(token, balance);
             self._unlock_();
         }
 
+        fn _get_managed(&self, token: AccountId) -> u128 {
+            return self.managers.get(&token).map(|m| m.managed).unwrap_or(0);
+        }
+
+        fn _require_cash(&self, token: AccountId, amount: u128) {
+            let balance = self._get_record(token).unwrap().balance;
+            let cash = self.math.bsub(balance, self._get_managed(token));
+            assert!(amount <= cash, "ERR_INSUFFICIENT_CASH");
+        }
+
+        fn _require_manager(&self, token: AccountId) -> AssetManager {
+            assert!(self._get_record(token).unwrap().bound, "ERR_NOT_BOUND");
+            let m = self.managers.get(&token).cloned().expect("ERR_NO_ASSET_MANAGER");
+            assert!(m.manager == self._get_sender(), "ERR_NOT_ASSET_MANAGER");
+            return m;
+        }
+
+        #[ink(message)]
+        pub fn get_asset_manager(&self, token: AccountId) -> Option<AssetManager> {
+            self._view_lock_();
+            return self.managers.get(&token).cloned();
+        }
+
+        #[ink(message)]
+        pub fn get_cash(&self, token: AccountId) -> u128 {
+            self._view_lock_();
+            assert!(self._get_record(token).unwrap().bound, "ERR_NOT_BOUND");
+            let balance = self._get_record(token).unwrap().balance;
+            return self.math.bsub(balance, self._get_managed(token));
+        }
+
+        #[ink(message)]
+        pub fn get_managed(&self, token: AccountId) -> u128 {
+            self._view_lock_();
+            return self._get_managed(token);
+        }
+
+        /// Assigns `manager` to `token`; it may hold up to `max_ratio` of the token's balance.
+        /// Passing `None` removes the manager once all managed funds are returned.
+        /// Managers can only be changed before the pool is finalized.
+        #[ink(message)]
+        pub fn set_asset_manager(&mut self, token: AccountId, manager: Option<AccountId>, max_ratio: u128) {
+            self._lock_();
+            assert!(self.controller == self._get_sender(), "ERR_NOT_CONTROLLER");
+            assert!(!self.finalized, "ERR_IS_FINALIZED");
+            assert!(self._get_record(token).unwrap().bound, "ERR_NOT_BOUND");
+            assert!(max_ratio <= MAX_MANAGED_RATIO, "ERR_MAX_MANAGED_RATIO");
+
+            let managed = self._get_managed(token);
+            match manager {
+                Some(m) => {
+                    if let Some(old) = self.managers.get(&token) {
+                        assert!(old.manager == m || managed == 0, "ERR_MANAGED_BALANCE");
+                    }
+                    let last_report = self.managers.get(&token).map(|old| old.last_report).unwrap_or(0);
+                    self.managers.insert(token, AssetManager {
+                        manager: m,
+                        max_ratio,
+                        managed,
+                        last_report,
+                    });
+                }
+                None => {
+                    assert!(managed == 0, "ERR_MANAGED_BALANCE");
+                    self.managers.take(&token);
+                }
+            }
+
+            self.env().emit_event(LogAssetManager {
+                token: Some(token),
+                manager,
+                max_ratio,
+            });
+            self._unlock_();
+        }
+
+        /// Called by the asset manager to borrow idle cash from the pool.
+        #[ink(message)]
+        pub fn manager_withdraw(&mut self, token: AccountId, amount: u128) {
+            self._lock_();
+            let mut m = self._require_manager(token);
+            let balance = self._get_record(token).unwrap().balance;
+
+            m.managed = self.math.badd(m.managed, amount);
+            assert!(m.managed <= self.math.bmul(balance, m.max_ratio), "ERR_MAX_MANAGED_RATIO");
+            self.managers.insert(token, m.clone());
+
+            self.env().emit_event(LogManagedBalance {
+                token: Some(token),
+                manager: Some(m.manager),
+                managed: m.managed,
+                balance,
+            });
+            self._push_underlying(token, m.manager, amount);
+            self._unlock_();
+        }
+
+        /// Called by the asset manager to return cash to the pool.
+        #[ink(message)]
+        pub fn manager_deposit(&mut self, token: AccountId, amount: u128) {
+            self._lock_();
+            let mut m = self._require_manager(token);
+            let balance = self._get_record(token).unwrap().balance;
+            let this = self.env().account_id();
+
+            m.managed = self.math.bsub(m.managed, amount);
+            self.managers.insert(token, m.clone());
+
+            self.env().emit_event(LogManagedBalance {
+                token: Some(token),
+                manager: Some(m.manager),
+                managed: m.managed,
+                balance,
+            });
+            self._pull_underlying(token, m.manager, this, amount);
+            self._unlock_();
+        }
+
+        /// Called by the asset manager to report the current value of the funds it holds,
+        /// so yield (or losses) are reflected in the pool balance.
+        /// A single report may move the managed amount by at most `MAX_MANAGED_REPORT_CHANGE`,
+        /// and reports must be at least `MANAGED_REPORT_INTERVAL` blocks apart.
+        #[ink(message)]
+        pub fn report_managed_balance(&mut self, token: AccountId, managed: u128) {
+            self._lock_();
+            let mut m = self._require_manager(token);
+            let block = self.env().block_number();
+            assert!(m.last_report == 0 || block >= m.last_report + MANAGED_REPORT_INTERVAL, "ERR_REPORT_TOO_SOON");
+            let max_change = self.math.bmul(m.managed, MAX_MANAGED_REPORT_CHANGE);
+            let change = if managed > m.managed { managed - m.managed } else { m.managed - managed };
+            assert!(change <= max_change, "ERR_MANAGED_REPORT_CHANGE");
+            let old_balance = self._get_record(token).unwrap().balance;
+            let cash = self.math.bsub(old_balance, m.managed);
+            let balance = self.math.badd(cash, managed);
+
+            m.managed = managed;
+            m.last_report = block;
+            self.managers.insert(token, m.clone());
+            self._update_balance(token, balance);
+
+            self.env().emit_event(LogManagedBalance {
+                token: Some(token),
+                manager: Some(m.manager),
+                managed,
+                balance,
+            });
+            self._unlock_();
+        }
+
         fn require_valid_bound(&self, token_in: AccountId, token_out: AccountId) {
             assert!(self._get_record(token_in).unwrap().bound, "ERR_NOT_BOUND");
             assert!(self._get_record(token_out).unwrap().bound, "ERR_NOT_BOUND");
@@ -491,6 +1100,7 @@ mod pool {
 
             let out_record_balance = self._get_record(token_out).unwrap().balance;
             let out_record_de_norm = self._get_record(token_out).unwrap().de_norm;
+            let swap_fee = self._effective_swap_fee();
 
             let message = ink_prelude::format!("in_record_balance {:?}", in_record_balance);
             ink_env::debug_println!("{}",&message);
@@ -505,7 +1115,7 @@ mod pool {
             ink_env::debug_println!("{}",&message3);
 
             return self.base.calc_spot_price(in_record_balance, in_record_de_norm,
-                                             out_record_balance, out_record_de_norm, self.swap_fee);
+                                             out_record_balance, out_record_de_norm, swap_fee);
         }
 
         #[ink(message)]
@@ -523,6 +1133,34 @@ mod pool {
                                              out_record_balance, out_record_de_norm, 0);
         }
 
+        /// What `swap_exact_amount_in` would pay out for `token_amount_in` right now.
+        #[ink(message)]
+        pub fn get_amount_out(&self, token_in: AccountId, token_amount_in: u128, token_out: AccountId) -> u128 {
+            self.require_valid_bound(token_in, token_out);
+            let in_record = self._get_record(token_in).unwrap();
+            let out_record = self._get_record(token_out).unwrap();
+            return self.base.calc_out_given_in(in_record.balance,
+                                               in_record.de_norm,
+                                               out_record.balance,
+                                               out_record.de_norm,
+                                               token_amount_in,
+                                               self._effective_swap_fee());
+        }
+
+        /// What `swap_exact_amount_out` would take in for `token_amount_out` right now.
+        #[ink(message)]
+        pub fn get_amount_in(&self, token_in: AccountId, token_out: AccountId, token_amount_out: u128) -> u128 {
+            self.require_valid_bound(token_in, token_out);
+            let in_record = self._get_record(token_in).unwrap();
+            let out_record = self._get_record(token_out).unwrap();
+            return self.base.calc_in_given_out(in_record.balance,
+                                               in_record.de_norm,
+                                               out_record.balance,
+                                               out_record.de_norm,
+                                               token_amount_out,
+                                               self._effective_swap_fee());
+        }
+
         #[ink(message)]
         pub fn join_pool(&mut self, pool_amount_out: u128, max_amounts_in: Vec<u128>) {
             self._lock_();
@@ -587,6 +1225,7 @@ mod pool {
                 assert!(token_amount_out != 0, "ERR_MATH_APPROX");
                 let pos = i as usize;
                 assert!(token_amount_out >= min_amounts_out[pos]);
+                self._require_cash(t, token_amount_out);
                 let mut balance = self._get_record(t).unwrap().balance;
                 balance = self.math.bsub(balance, token_amount_out);
                 self._update_balance(t, balance);
@@ -613,7 +1252,8 @@ mod pool {
                                     token_amount_in: u128,
                                     token_out: AccountId,
                                     min_amount_out: u128,
-                                    max_price: u128) ->(u128, u128) {
+                                    max_price: u128,
+                                    referrer: Option<AccountId>) ->(u128, u128) {
             debug_println!("enter swap_exact_amount_in");
 
             self._lock_();
@@ -625,6 +1265,7 @@ mod pool {
 
             let out_record_balance = self._get_record(token_out).unwrap().balance;
             let out_record_de_norm = self._get_record(token_out).unwrap().de_norm;
+            let swap_fee = self._effective_swap_fee();
 
             let message = ink_prelude::format!("in_record_balance {:?}", in_record_balance);
             ink_env::debug_println!("{}",&message);
@@ -638,14 +1279,14 @@ mod pool {
             let message3 = ink_prelude::format!("out_record_de_norm {:?}", out_record_de_norm);
             ink_env::debug_println!("{}",&message3);
 
-            assert!(token_amount_in <= self.math.bmul(in_record_balance, MAX_IN_RATIO), "ERR_MAX_IN_RATIO");
+            assert!(token_amount_in <= self.math.bmul(in_record_balance, self.config.max_in_ratio), "ERR_MAX_IN_RATIO");
             debug_println!("token_amount_in is valid");
 
             let spot_price_before = self.base.calc_spot_price(in_record_balance,
                                                               in_record_de_norm,
                                                               out_record_balance,
                                                               out_record_de_norm,
-                                                              self.swap_fee);
+                                                              swap_fee);
             debug_println!("cal spot_price_before finish");
             assert!(spot_price_before <= max_price, "ERR_BAD_LIMIT_PRICE");
             debug_println!("spot_price_before is valid");
@@ -656,19 +1297,22 @@ mod pool {
                                                                out_record_balance,
                                                                out_record_de_norm,
                                                                token_amount_in,
-                                                               self.swap_fee);
+                                                               swap_fee);
             debug_println!("cal calc_out_given_in finish");
             assert!(token_amount_out >= min_amount_out, "ERR_LIMIT_OUT");
+            self._require_cash(token_out, token_amount_out);
             debug_println!("token_amount_out is valid");
 
-            let new_in_balance = self.math.badd(in_record_balance, token_amount_in);
+            // the referrer's share of the fee leaves the pool with the trade
+            let referral_fee = self._referral_fee(referrer, token_amount_in, swap_fee);
+            let new_in_balance = self.math.badd(in_record_balance, self.math.bsub(token_amount_in, referral_fee));
             let new_out_balance = self.math.bsub(out_record_balance, token_amount_out);
 
             let spot_price_after = self.base.calc_spot_price(new_in_balance,
                                                              in_record_de_norm,
                                                              new_out_balance,
                                                              out_record_de_norm,
-                                                             self.swap_fee);
+                                                             swap_fee);
 
             debug_println!("calc_spot_price finish");
 
@@ -681,6 +1325,7 @@ mod pool {
 
             self._update_balance(token_in, new_in_balance);
             self._update_balance(token_out, new_out_balance);
+            self._update_volatility(spot_price_before, spot_price_after);
 
             debug_println!("_update_balance finish");
 
@@ -692,10 +1337,12 @@ mod pool {
                 token_out: Some(token_out),
                 token_amount_in,
                 token_amount_out,
+                swap_fee,
             });
 
             self._pull_underlying(token_in, sender, this, token_amount_in);
             self._push_underlying(token_out, sender, token_amount_out);
+            self._pay_referral(referrer, sender, token_in, referral_fee);
             debug_println!("transfer finish");
 
             self._unlock_();
@@ -709,7 +1356,8 @@ mod pool {
                                      max_amount_in: u128,
                                      token_out: AccountId,
                                      token_amount_out: u128,
-                                     max_price: u128) ->(u128, u128) {
+                                     max_price: u128,
+                                     referrer: Option<AccountId>) ->(u128, u128) {
             self._lock_();
             debug_println!("enter swap_exact_amount_out");
             self.require_valid_bound_swap(token_in, token_out);
@@ -721,6 +1369,7 @@ mod pool {
 
             let out_record_balance = self._get_record(token_out).unwrap().balance;
             let out_record_de_norm = self._get_record(token_out).unwrap().de_norm;
+            let swap_fee = self._effective_swap_fee();
 
             let message = ink_prelude::format!("in_record_balance {:?}", in_record_balance);
             ink_env::debug_println!("{}",&message);
@@ -734,7 +1383,8 @@ mod pool {
             let message3 = ink_prelude::format!("out_record_de_norm {:?}", out_record_de_norm);
             ink_env::debug_println!("{}",&message3);
 
-            assert!(token_amount_out <= self.math.bmul(out_record_balance, MAX_OUT_RATIO), "ERR_MAX_OUT_RATIO");
+            assert!(token_amount_out <= self.math.bmul(out_record_balance, self.config.max_out_ratio), "ERR_MAX_OUT_RATIO");
+            self._require_cash(token_out, token_amount_out);
 
             debug_println!("token_amount_out is valid");
 
@@ -742,7 +1392,7 @@ mod pool {
                                                               in_record_de_norm,
                                                               out_record_balance,
                                                               out_record_de_norm,
-                                                              self.swap_fee);
+                                                              swap_fee);
 
             debug_println!("calc_spot_price finish");
 
@@ -755,20 +1405,22 @@ mod pool {
                                                               out_record_balance,
                                                               out_record_de_norm,
                                                               token_amount_out,
-                                                              self.swap_fee);
+                                                              swap_fee);
             debug_println!("calc_in_given_out finish");
 
             assert!(token_amount_in <= max_amount_in, "ERR_LIMIT_IN");
             debug_println!("token_amount_in valid");
 
-            let new_in_record_balance = self.math.badd(in_record_balance, token_amount_in);
+            // the referrer's share of the fee leaves the pool with the trade
+            let referral_fee = self._referral_fee(referrer, token_amount_in, swap_fee);
+            let new_in_record_balance = self.math.badd(in_record_balance, self.math.bsub(token_amount_in, referral_fee));
             let new_out_record_balance = self.math.bsub(out_record_balance, token_amount_out);
 
             let spot_price_after = self.base.calc_spot_price(new_in_record_balance,
                                                              in_record_de_norm,
                                                              new_out_record_balance,
                                                              out_record_de_norm,
-                                                             self.swap_fee);
+                                                             swap_fee);
 
             debug_println!("calc_spot_price finish1");
 
@@ -792,6 +1444,7 @@ mod pool {
 
             self._update_balance(token_in, new_in_record_balance);
             self._update_balance(token_out, new_out_record_balance);
+            self._update_volatility(spot_price_before, spot_price_after);
             debug_println!("_update_balance finish");
 
             let (sender, this) = self._get_sender_and_this();
@@ -802,16 +1455,114 @@ mod pool {
                 token_out: Some(token_out),
                 token_amount_in,
                 token_amount_out,
+                swap_fee,
             });
 
             self._pull_underlying(token_in, sender, this, token_amount_in);
             self._push_underlying(token_out, sender, token_amount_out);
+            self._pay_referral(referrer, sender, token_in, referral_fee);
             debug_println!("transfer finish");
 
             self._unlock_();
             return (token_amount_in, spot_price_after);
         }
 
+        fn _add_delta(deltas: &mut Vec<(AccountId, i128)>, token: AccountId, amount: i128) {
+            match deltas.iter_mut().find(|(t, _)| *t == token) {
+                Some((_, d)) => *d += amount,
+                None => deltas.push((token, amount)),
+            }
+        }
+
+        /// Applies `steps` in order and only transfers the net amount of each token.
+        /// A positive delta is paid in by the caller, a negative one is paid out;
+        /// each token's delta must not exceed its entry in `limits`.
+        #[ink(message)]
+        pub fn batch_swap(&mut self,
+                          steps: Vec<SwapStep>,
+                          limits: Vec<(AccountId, i128)>) -> Vec<(AccountId, i128)> {
+            self._lock_();
+            assert!(steps.len() > 0, "ERR_EMPTY_STEPS");
+
+            let (sender, this) = self._get_sender_and_this();
+            let swap_fee = self._effective_swap_fee();
+            let mut deltas: Vec<(AccountId, i128)> = Vec::new();
+            let mut last_amount_out: u128 = 0;
+
+            for step in steps.iter() {
+                self.require_valid_bound_swap(step.token_in, step.token_out);
+                let token_amount_in = if step.token_amount_in == 0 { last_amount_out } else { step.token_amount_in };
+                assert!(token_amount_in != 0, "ERR_MATH_APPROX");
+
+                let in_record_balance = self._get_record(step.token_in).unwrap().balance;
+                let in_record_de_norm = self._get_record(step.token_in).unwrap().de_norm;
+                let out_record_balance = self._get_record(step.token_out).unwrap().balance;
+                let out_record_de_norm = self._get_record(step.token_out).unwrap().de_norm;
+
+                assert!(token_amount_in <= self.math.bmul(in_record_balance, self.config.max_in_ratio), "ERR_MAX_IN_RATIO");
+
+                let spot_price_before = self.base.calc_spot_price(in_record_balance,
+                                                                  in_record_de_norm,
+                                                                  out_record_balance,
+                                                                  out_record_de_norm,
+                                                                  swap_fee);
+                let token_amount_out = self.base.calc_out_given_in(in_record_balance,
+                                                                   in_record_de_norm,
+                                                                   out_record_balance,
+                                                                   out_record_de_norm,
+                                                                   token_amount_in,
+                                                                   swap_fee);
+
+                let new_in_balance = self.math.badd(in_record_balance, token_amount_in);
+                let new_out_balance = self.math.bsub(out_record_balance, token_amount_out);
+                let spot_price_after = self.base.calc_spot_price(new_in_balance,
+                                                                 in_record_de_norm,
+                                                                 new_out_balance,
+                                                                 out_record_de_norm,
+                                                                 swap_fee);
+                assert!(spot_price_after >= spot_price_before, "ERR_MATH_APPROX");
+                assert!(spot_price_before <= self.math.bdiv(token_amount_in, token_amount_out), "ERR_MATH_APPROX");
+
+                self._update_balance(step.token_in, new_in_balance);
+                self._update_balance(step.token_out, new_out_balance);
+                self._update_volatility(spot_price_before, spot_price_after);
+
+                self.env().emit_event(LogSwap {
+                    caller: Some(sender),
+                    token_in: Some(step.token_in),
+                    token_out: Some(step.token_out),
+                    token_amount_in,
+                    token_amount_out,
+                    swap_fee,
+                });
+
+                let amount_in: i128 = token_amount_in.try_into().unwrap();
+                let amount_out: i128 = token_amount_out.try_into().unwrap();
+                Self::_add_delta(&mut deltas, step.token_in, amount_in);
+                Self::_add_delta(&mut deltas, step.token_out, -amount_out);
+                last_amount_out = token_amount_out;
+            }
+
+            for (token, delta) in deltas.iter() {
+                let limit = limits.iter().find(|(t, _)| t == token).map(|(_, l)| *l).expect("ERR_NO_LIMIT");
+                assert!(*delta <= limit, "ERR_LIMIT");
+            }
+
+            for (token, delta) in deltas.iter() {
+                if *delta > 0 {
+                    self._pull_underlying(*token, sender, this, (*delta).try_into().unwrap());
+                } else if *delta < 0 {
+                    let amount: u128 = (-*delta).try_into().unwrap();
+                    // balances are already net of `amount`, so cash left must stay non-negative
+                    assert!(self._get_record(*token).unwrap().balance >= self._get_managed(*token), "ERR_INSUFFICIENT_CASH");
+                    self._push_underlying(*token, sender, amount);
+                }
+            }
+
+            self._unlock_();
+            return deltas;
+        }
+
         fn require_finalize_bound(&self, token_in: AccountId) {
             assert!(self.finalized, "ERR_NOT_FINALIZED");
             assert!(self._get_record(token_in).unwrap().bound, "ERR_NOT_BOUND");
@@ -826,12 +1577,13 @@ mod pool {
             debug_println!("enter join_swap_extern_amount_in");
 
             self.require_finalize_bound(token_in);
-            assert!(token_amount_in <= self.math.bmul(self._get_record(token_in).unwrap().balance, MAX_IN_RATIO), "ERR_MAX_IN_RATIO");
+            assert!(token_amount_in <= self.math.bmul(self._get_record(token_in).unwrap().balance, self.config.max_in_ratio), "ERR_MAX_IN_RATIO");
 
 
             // @todo fix storage
             let in_record_balance = self._get_record(token_in).unwrap().balance;
             let in_record_de_norm = self._get_record(token_in).unwrap().de_norm;
+            let swap_fee = self._effective_swap_fee();
 
             let total_supply = self.token.total_supply();
 
@@ -841,7 +1593,7 @@ mod pool {
                                                                           total_supply,
                                                                           self.total_weight,
                                                                           token_amount_in,
-                                                                          self.swap_fee);
+                                                                          swap_fee);
             assert!(pool_amount_out >= min_pool_amount_out, "ERR_LIMIT_OUT");
             debug_println!("cal finish");
 
@@ -872,6 +1624,7 @@ mod pool {
             self.require_finalize_bound(token_in);
             let in_record_balance = self._get_record(token_in).unwrap().balance;
             let in_record_de_norm = self._get_record(token_in).unwrap().de_norm;
+            let swap_fee = self._effective_swap_fee();
 
             let total_supply = self.token.total_supply();
             debug_println!("ready to cal");
@@ -880,13 +1633,13 @@ mod pool {
                                                                           total_supply,
                                                                           self.total_weight,
                                                                           pool_amount_out,
-                                                                          self.swap_fee);
+                                                                          swap_fee);
             debug_println!("cal  finish");
             assert!(token_amount_in != 0, "ERR_MATH_APPROX");
             debug_println!("cal  finish1");
             assert!(token_amount_in <= max_amount_in, "ERR_LIMIT_IN");
             debug_println!("cal  finish2");
-            assert!(token_amount_in <= self.math.bmul(in_record_balance, MAX_IN_RATIO), "ERR_MAX_IN_RATIO");
+            assert!(token_amount_in <= self.math.bmul(in_record_balance, self.config.max_in_ratio), "ERR_MAX_IN_RATIO");
             debug_println!("cal  finish3");
             self._update_balance(token_in, self.math.badd(in_record_balance, token_amount_in));
             let (sender, this) = self._get_sender_and_this();
@@ -916,6 +1669,7 @@ mod pool {
             // @todo fix storage
             let out_record_balance = self._get_record(token_out).unwrap().balance;
             let out_record_de_norm = self._get_record(token_out).unwrap().de_norm;
+            let swap_fee = self._effective_swap_fee();
 
             let total_supply = self.token.total_supply();
 
@@ -925,10 +1679,11 @@ mod pool {
                 total_supply,
                 self.total_weight,
                 pool_amount_in,
-                self.swap_fee);
+                swap_fee);
 
             assert!(token_amount_out >= min_amount_out, "ERR_LIMIT_OUT");
-            assert!(token_amount_out <= self.math.bmul(out_record_balance, MAX_OUT_RATIO), "ERR_MAX_OUT_RATIO");
+            assert!(token_amount_out <= self.math.bmul(out_record_balance, self.config.max_out_ratio), "ERR_MAX_OUT_RATIO");
+            self._require_cash(token_out, token_amount_out);
 
             self._update_balance(token_out, self.math.bsub(out_record_balance, token_amount_out));
             let exit_fee = self.math.bmul(pool_amount_in, EXIT_FEE);
@@ -956,11 +1711,12 @@ mod pool {
                                            max_pool_amount_in: u128) -> u128 {
             self._lock_();
             self.require_finalize_bound(token_out);
-            assert!(token_amount_out <= self.math.bmul(self._get_record(token_out).unwrap().balance, MAX_OUT_RATIO), "ERR_MAX_OUT_RATIO");
+            assert!(token_amount_out <= self.math.bmul(self._get_record(token_out).unwrap().balance, self.config.max_out_ratio), "ERR_MAX_OUT_RATIO");
 
             // @todo fix storage
             let out_record_balance = self._get_record(token_out).unwrap().balance;
             let out_record_de_norm = self._get_record(token_out).unwrap().de_norm;
+            let swap_fee = self._effective_swap_fee();
 
             let total_supply = self.token.total_supply();
             let pool_amount_in = self.base.calc_pool_in_given_single_out(
@@ -969,11 +1725,12 @@ mod pool {
                 total_supply,
                 self.total_weight,
                 token_amount_out,
-                self.swap_fee
+                swap_fee
             );
 
             assert!(pool_amount_in != 0, "ERR_MATH_APPROX");
             assert!(pool_amount_in <= max_pool_amount_in, "ERR_LIMIT_IN");
+            self._require_cash(token_out, token_amount_out);
 
             self._update_balance(token_out, self.math.bsub(out_record_balance, token_amount_out));
             let sender = self._get_sender();
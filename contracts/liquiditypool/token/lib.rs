@@ -1,6 +1,6 @@
 #![cfg_attr(not(feature = "std"), no_std)]
 
-pub use self::token::Token;
+pub use self::token::{Token, Checkpoint, Error, Result};
 use ink_lang as ink;
 
 #[ink::contract]
@@ -13,9 +13,43 @@ mod token {
         collections::HashMap as StorageHashMap,
         Lazy
     };
+    use ink_storage::traits::{PackedLayout, SpreadLayout};
 
     use ink_env::call::FromAccountId;
-    use ink_prelude::string::ToString;
+    use ink_env::hash::Blake2x256;
+
+    /// Domain tag of the payload signed for `permit`.
+    pub const PERMIT_TAG: &[u8] = b"CPT_PERMIT";
+
+    /// The pool token error types.
+    #[derive(Debug, PartialEq, Eq, scale::Encode, scale::Decode)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub enum Error {
+        /// Returned if not enough balance to fulfill a request is available.
+        InsufficientBalance,
+        /// Returned if not enough allowance to fulfill a request is available.
+        InsufficientAllowance,
+        /// Returned if a permit is used after its deadline.
+        PermitExpired,
+        /// Returned if a permit signature does not recover to the owner.
+        InvalidSignature,
+    }
+
+    /// The pool token result type.
+    pub type Result<T> = core::result::Result<T, Error>;
+
+    /// Value of a balance (or the total supply) from `block` on.
+    #[derive(
+    Debug, PartialEq, Eq, Clone, Copy, scale::Encode, scale::Decode, SpreadLayout, PackedLayout,
+    )]
+    #[cfg_attr(
+    feature = "std",
+    derive(scale_info::TypeInfo, ink_storage::traits::StorageLayout)
+    )]
+    pub struct Checkpoint {
+        pub block: BlockNumber,
+        pub value: u128,
+    }
 
     #[ink(storage)]
     pub struct Token {
@@ -24,6 +58,11 @@ mod token {
         total_supply: u128,
         balances: StorageHashMap<AccountId, u128>,
         allowances: StorageHashMap<(AccountId, AccountId), u128>,
+        nonces: StorageHashMap<AccountId, u64>,
+        checkpoints: StorageHashMap<(AccountId, u32), Checkpoint>,
+        num_checkpoints: StorageHashMap<AccountId, u32>,
+        supply_checkpoints: StorageHashMap<u32, Checkpoint>,
+        num_supply_checkpoints: u32,
         name: Option<String>,
         symbol: Option<String>,
         decimals: Option<u8>,
@@ -54,7 +93,10 @@ mod token {
 
     impl Token {
         #[ink(constructor)]
-        pub fn new(math_address: AccountId) -> Self {
+        pub fn new(math_address: AccountId,
+                   name: Option<String>,
+                   symbol: Option<String>,
+                   decimals: Option<u8>) -> Self {
             let controller = Self::env().caller();
             let math: Math = FromAccountId::from_account_id(math_address);
             Self {
@@ -63,10 +105,15 @@ mod token {
                 total_supply: 0,
                 balances: StorageHashMap::new(),
                 allowances: StorageHashMap::new(),
-
-                name: Some("Conversation Pool Token".to_string()),
-                symbol: Some("CPT".to_string()),
-                decimals: Some(10),
+                nonces: StorageHashMap::new(),
+                checkpoints: StorageHashMap::new(),
+                num_checkpoints: StorageHashMap::new(),
+                supply_checkpoints: StorageHashMap::new(),
+                num_supply_checkpoints: 0,
+
+                name,
+                symbol,
+                decimals: Some(decimals.unwrap_or(10)),
             }
         }
 
@@ -77,8 +124,9 @@ mod token {
 
             let balance = self.balance_of(from);
             let balance = self.math.badd(balance, amt);
-            self.balances.insert(from, balance);
-            self.total_supply = self.math.badd(self.total_supply, amt);
+            self._set_balance(from, balance);
+            let total_supply = self.math.badd(self.total_supply, amt);
+            self._set_total_supply(total_supply);
 
             self.env().emit_event(Transfer {
                 from: None,
@@ -95,8 +143,9 @@ mod token {
             let balance = self.balance_of(from);
             assert!(balance >= amt, "ERR_INSUFFICIENT_BAL");
             let balance = self.math.bsub(balance, amt);
-            self.balances.insert(from, balance);
-            self.total_supply = self.math.bsub(self.total_supply, amt);
+            self._set_balance(from, balance);
+            let total_supply = self.math.bsub(self.total_supply, amt);
+            self._set_total_supply(total_supply);
             self.env().emit_event(Transfer {
                 from: Some(from),
                 to: None,
@@ -104,23 +153,92 @@ mod token {
             });
         }
 
+        fn _set_balance(&mut self, owner: AccountId, balance: u128) {
+            self.balances.insert(owner, balance);
+
+            let block = self.env().block_number();
+            let n = self.num_checkpoints.get(&owner).copied().unwrap_or(0);
+            if n > 0 && self.checkpoints[&(owner, n - 1)].block == block {
+                self.checkpoints[&(owner, n - 1)].value = balance;
+            } else {
+                self.checkpoints.insert((owner, n), Checkpoint { block, value: balance });
+                self.num_checkpoints.insert(owner, n + 1);
+            }
+        }
+
+        fn _set_total_supply(&mut self, total_supply: u128) {
+            self.total_supply = total_supply;
+
+            let block = self.env().block_number();
+            let n = self.num_supply_checkpoints;
+            if n > 0 && self.supply_checkpoints[&(n - 1)].block == block {
+                self.supply_checkpoints[&(n - 1)].value = total_supply;
+            } else {
+                self.supply_checkpoints.insert(n, Checkpoint { block, value: total_supply });
+                self.num_supply_checkpoints = n + 1;
+            }
+        }
+
+        // Binary search for the last checkpoint at or before `block`
+        fn _checkpoint_lookup<F>(n: u32, block: BlockNumber, get: F) -> u128
+            where F: Fn(u32) -> Checkpoint {
+            if n == 0 || get(0).block > block {
+                return 0;
+            }
+
+            let (mut lower, mut upper) = (0, n - 1);
+            while upper > lower {
+                let center = upper - (upper - lower) / 2;
+                let cp = get(center);
+                if cp.block == block {
+                    return cp.value;
+                } else if cp.block < block {
+                    lower = center;
+                } else {
+                    upper = center - 1;
+                }
+            }
+            return get(lower).value;
+        }
+
+        /// Balance of `owner` at the end of `block`, which must already be finished.
         #[ink(message)]
-        pub fn trans(&mut self, from:AccountId, to:AccountId, amt:u128) {
-            assert!(self.controller == Self::env().caller(), "ERR_NOT_CONTROLLER");
+        pub fn balance_of_at(&self, owner: AccountId, block: BlockNumber) -> u128 {
+            assert!(block < self.env().block_number(), "ERR_NOT_YET_DETERMINED");
+            let n = self.num_checkpoints.get(&owner).copied().unwrap_or(0);
+            Self::_checkpoint_lookup(n, block, |i| self.checkpoints[&(owner, i)])
+        }
+
+        /// Total supply at the end of `block`, which must already be finished.
+        #[ink(message)]
+        pub fn total_supply_at(&self, block: BlockNumber) -> u128 {
+            assert!(block < self.env().block_number(), "ERR_NOT_YET_DETERMINED");
+            Self::_checkpoint_lookup(self.num_supply_checkpoints, block, |i| self.supply_checkpoints[&i])
+        }
+
+        #[ink(message)]
+        pub fn num_checkpoints(&self, owner: AccountId) -> u32 {
+            self.num_checkpoints.get(&owner).copied().unwrap_or(0)
+        }
+
+        fn _move(&mut self, from: AccountId, to: AccountId, amt: u128) -> Result<()> {
             let from_balance = self.balance_of(from);
-            assert!(from_balance >= amt, "ERR_INSUFFICIENT_BAL");
+            if from_balance < amt {
+                return Err(Error::InsufficientBalance);
+            }
             let from_balance = self.math.bsub(from_balance, amt);
-            self.balances.insert(from, from_balance);
+            self._set_balance(from, from_balance);
 
             let to_balance = self.balance_of(to);
             let to_balance = self.math.badd(to_balance, amt);
-            self.balances.insert(to, to_balance);
+            self._set_balance(to, to_balance);
 
             self.env().emit_event(Transfer {
                 from: Some(from),
                 to: Some(to),
                 value: amt,
             });
+            Ok(())
         }
 
         #[ink(message)]
@@ -128,7 +246,7 @@ mod token {
             let from = self.env().caller();
             assert!(self.controller == from, "ERR_NOT_CONTROLLER");
 
-            self.trans(from, to, amt);
+            self._move(from, to, amt).expect("ERR_INSUFFICIENT_BAL");
         }
 
         #[ink(message)]
@@ -136,7 +254,7 @@ mod token {
             let to = self.env().caller();
             assert!(self.controller == to, "ERR_NOT_CONTROLLER");
 
-            self.trans(from, to, amt);
+            self._move(from, to, amt).expect("ERR_INSUFFICIENT_BAL");
         }
 
         #[ink(message)]
@@ -169,46 +287,104 @@ mod token {
             self.total_supply
         }
 
+        /// Allows `spender` to withdraw from the caller's account multiple times, up to
+        /// the `value` amount.
         #[ink(message)]
-        pub fn approve(&mut self, spender: AccountId, value: u128)  {
+        pub fn approve(&mut self, spender: AccountId, value: u128) -> Result<()> {
             let owner = self.env().caller();
-            assert!(self.controller == owner, "ERR_NOT_CONTROLLER");
-
-            self.allowances.insert((owner, spender), value);
-            self.env().emit_event(Approval {
-                owner,
-                spender,
-                value,
-            });
+            self._approve(owner, spender, value);
+            Ok(())
         }
 
         #[ink(message)]
-        pub fn increase_approval(&mut self, spender: AccountId, value: u128) {
+        pub fn increase_approval(&mut self, spender: AccountId, value: u128) -> Result<()> {
             let owner = self.env().caller();
-            assert!(self.controller == owner, "ERR_NOT_CONTROLLER");
-
-            let balance = self.allowance(owner, spender);
-            let balance = self.math.badd(balance, value);
-            self.allowances.insert((owner, spender), balance);
-            self.env().emit_event(Approval {
-                owner,
-                spender,
-                value,
-            });
+            let allowance = self.allowance(owner, spender);
+            let allowance = self.math.badd(allowance, value);
+            self._approve(owner, spender, allowance);
+            Ok(())
         }
 
         #[ink(message)]
-        pub fn decrease_approval(&mut self, spender: AccountId, value: u128) {
+        pub fn decrease_approval(&mut self, spender: AccountId, value: u128) -> Result<()> {
             let owner = self.env().caller();
-            assert!(self.controller == owner, "ERR_NOT_CONTROLLER");
-
             let old_value = self.allowance(owner, spender);
             if value > old_value {
-                self.allowances.insert((owner, spender), 0);
+                self._approve(owner, spender, 0);
             } else {
                 let new_value = self.math.bsub(old_value, value);
-                self.allowances.insert((owner, spender), new_value);
+                self._approve(owner, spender, new_value);
             }
+            Ok(())
+        }
+
+        /// Transfers `value` amount of tokens from the caller's account to account `to`.
+        #[ink(message)]
+        pub fn transfer(&mut self, to: AccountId, value: u128) -> Result<()> {
+            let owner = self.env().caller();
+            self._move(owner, to, value)
+        }
+
+        /// Transfers `value` tokens on the behalf of `from` to the account `to`.
+        /// An allowance of `u128::MAX` is never decreased.
+        #[ink(message)]
+        pub fn transfer_from(&mut self, from: AccountId, to: AccountId, value: u128) -> Result<()> {
+            let spender = self.env().caller();
+            if spender == from {
+                return self._move(from, to, value);
+            }
+
+            let allow = self.allowance(from, spender);
+            if allow < value {
+                return Err(Error::InsufficientAllowance);
+            }
+            self._move(from, to, value)?;
+
+            if allow != u128::MAX {
+                let allow = self.math.bsub(allow, value);
+                self._approve(from, spender, allow);
+            }
+            Ok(())
+        }
+
+        /// Next permit nonce of `owner`.
+        #[ink(message)]
+        pub fn nonces(&self, owner: AccountId) -> u64 {
+            self.nonces.get(&owner).copied().unwrap_or(0)
+        }
+
+        /// Sets the allowance of `spender` over `owner`'s tokens from an off-chain signature.
+        ///
+        /// `signature` is an ECDSA signature by `owner` over the SCALE-encoded
+        /// `(PERMIT_TAG, this contract, owner, spender, value, nonce, deadline)`;
+        /// `owner` must be the blake2-256 hash of the signing public key.
+        #[ink(message)]
+        pub fn permit(&mut self,
+                      owner: AccountId,
+                      spender: AccountId,
+                      value: u128,
+                      deadline: Timestamp,
+                      signature: [u8; 65]) -> Result<()> {
+            if self.env().block_timestamp() > deadline {
+                return Err(Error::PermitExpired);
+            }
+
+            let nonce = self.nonces(owner);
+            let payload = (PERMIT_TAG, self.env().account_id(), owner, spender, value, nonce, deadline);
+            let mut message_hash = [0u8; 32];
+            ink_env::hash_encoded::<Blake2x256, _>(&payload, &mut message_hash);
+
+            if ecdsa::recover_signer(&signature, &message_hash).map(AccountId::from) != Some(owner) {
+                return Err(Error::InvalidSignature);
+            }
+
+            self.nonces.insert(owner, nonce + 1);
+            self._approve(owner, spender, value);
+            Ok(())
+        }
+
+        fn _approve(&mut self, owner: AccountId, spender: AccountId, value: u128) {
+            self.allowances.insert((owner, spender), value);
             self.env().emit_event(Approval {
                 owner,
                 spender,
@@ -216,34 +392,17 @@ mod token {
             });
         }
 
+        /// Sets the token name and symbol, e.g. derived from the bound tokens at finalize.
         #[ink(message)]
-        pub fn transfer(&mut self, to: AccountId, value: u128) -> bool {
-            let owner = self.env().caller();
-            assert!(self.controller == owner, "ERR_NOT_CONTROLLER");
-
-            self.trans(owner, to, value);
-            return true;
+        pub fn set_metadata(&mut self, name: Option<String>, symbol: Option<String>) {
+            assert!(self.controller == Self::env().caller(), "ERR_NOT_CONTROLLER");
+            self.name = name;
+            self.symbol = symbol;
         }
 
         #[ink(message)]
-        pub fn transfer_from(&mut self, from: AccountId, to: AccountId, value: u128) -> bool {
-            let owner = self.env().caller();
-            assert!(self.controller == owner, "ERR_NOT_CONTROLLER");
-
-            let allow = self.allowance(from, owner);
-            assert!(owner == from || value < allow, "ERR_BTOKEN_BAD_CALLER");
-            self.trans(from, to, value);
-
-            if owner != from && allow != u128::MAX {
-                let balance = self.math.bsub(allow, value);
-                self.allowances.insert((from, owner), balance);
-                self.env().emit_event(Approval {
-                    owner,
-                    spender: to,
-                    value: balance,
-                });
-            }
-            return true;
+        pub fn get_controller(&self) -> AccountId {
+            self.controller
         }
 
         #[ink(message)]
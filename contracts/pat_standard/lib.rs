@@ -9,11 +9,15 @@ mod pat {
     use ink_prelude::string::String;
     use ownership::Ownable;
     use ink_env::debug_println;
+    use ink_env::hash::Blake2x256;
     #[cfg(not(feature = "ink-as-dependency"))]
     use ink_lang as ink;
     #[cfg(not(feature = "ink-as-dependency"))]
     use ink_storage::{collections::HashMap as StorageHashMap, lazy::Lazy};
 
+    /// Domain tag of the payload signed for `permit`.
+    pub const PERMIT_TAG: &[u8] = b"PAT_PERMIT";
+
     /// The ERC-20 error types.
     #[derive(Debug, PartialEq, Eq, scale::Encode)]
     #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
@@ -28,6 +32,10 @@ mod pat {
         OnlyOwnerAccess,
         InvalidNewOwner,
         NotBlacklistedUser,
+        /// Returned if a permit is used after its deadline.
+        PermitExpired,
+        /// Returned if a permit signature does not recover to the owner.
+        InvalidSignature,
     }
 
     /// The ERC-20 result type.
@@ -90,6 +98,8 @@ mod pat {
         owner: AccountId,
 
         blacklisted: StorageHashMap<AccountId, bool>,
+        /// Next permit nonce of each owner.
+        nonces: StorageHashMap<AccountId, u64>,
     }
 
     /// Event emitted when a token transfer occurs.
@@ -178,6 +188,7 @@ mod pat {
                 pause: false,
                 owner: caller,
                 blacklisted: Default::default(),
+                nonces: Default::default(),
             };
             Self::env().emit_event(Transfer {
                 from: None,
@@ -423,6 +434,57 @@ mod pat {
             Ok(())
         }
 
+        /// Returns the next permit nonce of `owner`.
+        #[ink(message)]
+        pub fn nonces(&self, owner: AccountId) -> u64 {
+            self.nonces.get(&owner).copied().unwrap_or(0)
+        }
+
+        /// Sets the allowance of `spender` over `owner`'s tokens from an off-chain signature.
+        ///
+        /// `signature` is an ECDSA signature by `owner` over the SCALE-encoded
+        /// `(PERMIT_TAG, this contract, owner, spender, value, nonce, deadline)`,
+        /// where `owner` is the blake2-256 hash of the signing public key.
+        ///
+        /// An `Approval` event is emitted.
+        ///
+        /// # Errors
+        ///
+        /// Returns `PermitExpired` error if the block timestamp is past `deadline`.
+        ///
+        /// Returns `InvalidSignature` error if the signature does not recover to `owner`.
+        #[ink(message)]
+        pub fn permit(
+            &mut self,
+            owner: AccountId,
+            spender: AccountId,
+            value: Balance,
+            deadline: Timestamp,
+            signature: [u8; 65],
+        ) -> Result<()> {
+            if self.env().block_timestamp() > deadline {
+                return Err(Error::PermitExpired);
+            }
+
+            let nonce = self.nonces(owner);
+            let payload = (PERMIT_TAG, self.env().account_id(), owner, spender, value, nonce, deadline);
+            let mut message_hash = [0u8; 32];
+            ink_env::hash_encoded::<Blake2x256, _>(&payload, &mut message_hash);
+
+            if ecdsa::recover_signer(&signature, &message_hash).map(AccountId::from) != Some(owner) {
+                return Err(Error::InvalidSignature);
+            }
+
+            self.nonces.insert(owner, nonce + 1);
+            self.allowances.insert((owner, spender), value);
+            self.env().emit_event(Approval {
+                owner,
+                spender,
+                value,
+            });
+            Ok(())
+        }
+
         /// Transfers `value` amount of tokens from the caller's account to account `to`.
         ///
         /// On success a `Transfer` event is emitted.
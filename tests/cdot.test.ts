@@ -0,0 +1,65 @@
+import BN from 'bn.js';
+import { expect } from 'chai';
+import { network, patract } from 'redspot';
+import { BONE, deployOptions, newSigner } from './helpers';
+
+const { getContractFactory } = patract;
+
+const { api } = network;
+
+describe('cDOT', () => {
+  after(() => {
+    return api.disconnect();
+  });
+
+  async function freeBalance(address: any): Promise<BN> {
+    const account = await api.query.system.account(address);
+    return new BN(account.data.free.toString());
+  }
+
+  async function setup() {
+    const sender = await newSigner(10000);
+    const cdot = await (await getContractFactory('cdot', sender))
+      .deploy('new', 'Coinversation DOT', 'cDOT', 10, deployOptions);
+    return { sender, cdot };
+  }
+
+  async function cdotBalance(cdot: any, address: any): Promise<string> {
+    return new BN((await cdot.query.balanceOf(address)).output!.toString()).toString();
+  }
+
+  it('mints as much cDOT as native DOT is deposited', async () => {
+    const { sender, cdot } = await setup();
+    const held = await freeBalance(cdot.address);
+
+    await expect(cdot.tx.deposit({ value: BONE.muln(10) })).to.emit(cdot, 'Deposit');
+    await cdot.tx.deposit({ value: BONE.muln(5) });
+    expect(await cdotBalance(cdot, sender.address)).to.equal(BONE.muln(15).toString());
+    expect((await cdot.query.totalSupply()).output!.toString()).to.equal(BONE.muln(15).toString());
+    expect((await freeBalance(cdot.address)).sub(held).toString()).to.equal(BONE.muln(15).toString());
+  });
+
+  it('burns cDOT and pays back native DOT on withdraw', async () => {
+    const { sender, cdot } = await setup();
+    await cdot.tx.deposit({ value: BONE.muln(10) });
+    const held = await freeBalance(cdot.address);
+
+    await expect(cdot.tx.withdraw(BONE.muln(4))).to.emit(cdot, 'Withdrawal');
+    expect(await cdotBalance(cdot, sender.address)).to.equal(BONE.muln(6).toString());
+    expect((await cdot.query.totalSupply()).output!.toString()).to.equal(BONE.muln(6).toString());
+    expect(held.sub(await freeBalance(cdot.address)).toString()).to.equal(BONE.muln(4).toString());
+  });
+
+  it('does not withdraw more than the caller holds', async () => {
+    const { sender, cdot } = await setup();
+    await cdot.tx.deposit({ value: BONE.muln(10) });
+    const other = await newSigner(100);
+    await cdot.connect(other).tx.deposit({ value: BONE.muln(10) });
+
+    const outcome: any = (await cdot.query.withdraw(BONE.muln(11))).output;
+    expect(outcome.isErr).to.be.true;
+    await expect(cdot.tx.withdraw(BONE.muln(11))).to.not.emit(cdot, 'Withdrawal');
+    expect(await cdotBalance(cdot, sender.address)).to.equal(BONE.muln(10).toString());
+    expect((await cdot.query.totalSupply()).output!.toString()).to.equal(BONE.muln(20).toString());
+  });
+});
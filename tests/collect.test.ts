@@ -0,0 +1,76 @@
+import BN from 'bn.js';
+import { expect } from 'chai';
+import { network } from 'redspot';
+import { BONE, balanceOf, deployFactory, deployPat, lpToken, newFactoryPool, newSigner } from './helpers';
+
+const { api } = network;
+
+const POOL_BALANCE = BONE.muln(1000);
+const SHARES = BONE.muln(10);
+
+describe('Factory fee collection', () => {
+  after(() => {
+    return api.disconnect();
+  });
+
+  async function setup() {
+    const labs = await newSigner(100000);
+    const treasury = await newSigner(1);
+    const { factory } = await deployFactory(labs);
+    const dot = await deployPat(labs, 'Coinversation DOT Token', 'Cdot');
+    const usd = await deployPat(labs, 'Coinversation USD Token', 'Cusd');
+    const pools: any[] = [];
+    const lps: any[] = [];
+    for (const salt of [1, 2]) {
+      const pool = await newFactoryPool(factory, labs, salt, [dot, usd], [POOL_BALANCE, POOL_BALANCE]);
+      const lp = await lpToken(pool, labs);
+      // shares sent to the factory, as protocol fees would be
+      await lp.tx.transfer(factory.address, SHARES);
+      pools.push(pool);
+      lps.push(lp);
+    }
+    await factory.tx.setTreasury(treasury.address);
+
+    return { treasury, factory, dot, usd, pools, lps };
+  }
+
+  async function shares(lp: any, address: any): Promise<string> {
+    return new BN((await lp.query.balanceOf(address)).output!.toString()).toString();
+  }
+
+  it('forwards collected shares to the treasury', async () => {
+    const { treasury, factory, pools, lps } = await setup();
+
+    await expect(factory.tx.collect(pools[0].address)).to.emit(factory, 'LogCollect');
+    expect(await shares(lps[0], treasury.address)).to.equal(SHARES.toString());
+    expect(await shares(lps[0], factory.address)).to.equal('0');
+  });
+
+  it('exits collected shares into the underlying tokens when set to', async () => {
+    const { treasury, factory, dot, usd, pools, lps } = await setup();
+    await factory.tx.setCollectExit(true);
+
+    await factory.tx.collect(pools[0].address);
+    expect(await shares(lps[0], factory.address)).to.equal('0');
+    expect(await shares(lps[0], treasury.address)).to.equal('0');
+    // 10 of 100 shares of a pool holding 1000 of each token
+    for (const token of [dot, usd]) {
+      expect((await balanceOf(token, treasury.address)).toString()).to.equal(BONE.muln(100).toString());
+      expect((await balanceOf(token, factory.address)).toString()).to.equal('0');
+    }
+  });
+
+  it('skips deregistered pools in a batch', async () => {
+    const { treasury, factory, pools, lps } = await setup();
+    await factory.tx.deregisterPool(pools[1].address);
+
+    await factory.tx.collectMany([pools[1].address, pools[0].address]);
+    expect(await shares(lps[0], treasury.address)).to.equal(SHARES.toString());
+    expect(await shares(lps[1], treasury.address)).to.equal('0');
+    expect(await shares(lps[1], factory.address)).to.equal(SHARES.toString());
+
+    const outcome = await factory.query.collect(pools[1].address);
+    expect(outcome.result.isErr).to.be.true;
+    expect(outcome.debugMessage.toString()).to.contain('ERR_NOT_POOL');
+  });
+});
@@ -0,0 +1,72 @@
+import BN from 'bn.js';
+import { expect } from 'chai';
+import { network } from 'redspot';
+import { deployFactory, newSigner, one } from './helpers';
+
+const { api } = network;
+
+describe('Factory pool creation charges', () => {
+  after(() => {
+    return api.disconnect();
+  });
+
+  async function freeBalance(address: any): Promise<BN> {
+    const account = await api.query.system.account(address);
+    return new BN(account.data.free.toString());
+  }
+
+  async function setup() {
+    const labs = await newSigner(100000);
+    const creator = await newSigner(100000);
+    const { factory } = await deployFactory(labs);
+    return { labs, creator, factory: factory.connect(creator) };
+  }
+
+  // arguments of `new_pool` with 10 + 10 units of endowments
+  function poolArgs(salt: number): any[] {
+    return [salt, 1, one().muln(10), one().muln(10), null, null, null, null];
+  }
+
+  function endowments(): BN {
+    return one().muln(20);
+  }
+
+  it('makes the creator fund the endowments', async () => {
+    const { factory } = await setup();
+
+    for (const value of [new BN(0), one().muln(10)]) {
+      const outcome = await factory.query.newPool(...poolArgs(1), { value });
+      expect(outcome.result.isErr).to.be.true;
+      expect(outcome.debugMessage.toString()).to.contain('ERR_CREATION_FEE');
+    }
+
+    const before = await freeBalance(factory.address);
+    await expect(factory.tx.newPool(...poolArgs(1), { value: endowments() })).to.emit(factory, 'LogNewPool');
+    expect((await freeBalance(factory.address)).toString()).to.equal(before.toString());
+  });
+
+  it('pays a native creation fee to labs on top of the endowments', async () => {
+    const { labs, factory } = await setup();
+    const fee = one().muln(5);
+    await factory.connect(labs).tx.setCreationFee(null, fee);
+
+    const outcome = await factory.query.newPool(...poolArgs(1), { value: endowments() });
+    expect(outcome.result.isErr).to.be.true;
+    expect(outcome.debugMessage.toString()).to.contain('ERR_CREATION_FEE');
+
+    const labsBefore = await freeBalance(labs.address);
+    await factory.tx.newPool(...poolArgs(1), { value: endowments().add(fee) });
+    expect((await freeBalance(labs.address)).sub(labsBefore).toString()).to.equal(fee.toString());
+    expect((await factory.query.getPoolCount()).output!.toString()).to.equal('1');
+  });
+
+  it('limits the pools an account creates per period', async () => {
+    const { labs, factory } = await setup();
+    await factory.connect(labs).tx.setCreationLimit(1, 1000);
+
+    await factory.tx.newPool(...poolArgs(1), { value: endowments() });
+    const outcome = await factory.query.newPool(...poolArgs(2), { value: endowments() });
+    expect(outcome.result.isErr).to.be.true;
+    expect(outcome.debugMessage.toString()).to.contain('ERR_CREATION_LIMIT');
+  });
+});
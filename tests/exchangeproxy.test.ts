@@ -0,0 +1,183 @@
+import BN from 'bn.js';
+import { expect } from 'chai';
+import { patract, network } from 'redspot';
+import { BONE, MAX_PRICE, deployFactory, deployOptions, deployPat, newSigner, one } from './helpers';
+
+const { getContractFactory, getRandomSigner } = patract;
+
+const { api } = network;
+
+const POOL_DOT = BONE.muln(1000);
+const POOL_USD = BONE.muln(1000);
+// upper bound on the transaction fee of one proxy call on the dev chain
+const GAS_TOLERANCE = BONE;
+
+describe('ExchangeProxy native DOT', () => {
+  after(() => {
+    return api.disconnect();
+  });
+
+  async function freeBalance(address: any): Promise<BN> {
+    const account = await api.query.system.account(address);
+    return new BN(account.data.free.toString());
+  }
+
+  async function setup() {
+    const sender = await newSigner(100000);
+    const trader = await newSigner(100000);
+    const { factory } = await deployFactory(sender);
+
+    const cdot = await (await getContractFactory('cdot', sender))
+      .deploy('new', 'Coinversation DOT', 'cDOT', 10, deployOptions);
+    const usd = await deployPat(sender, 'Coinversation USD Token', 'Cusd');
+
+    // a 50/50 cDOT/USD pool at a price of 1
+    await cdot.tx.deposit({ value: POOL_DOT });
+    await cdot.tx.approve(factory.address, POOL_DOT);
+    await usd.tx['iPat,approve'](factory.address, POOL_USD);
+    const salt = 1;
+    const poolAddress = (await factory.query.predictPoolAddress(salt)).output?.toString();
+    await factory.tx.newFinalizedPool(
+      salt,
+      [cdot.address, usd.address],
+      [POOL_DOT, POOL_USD],
+      [BONE.muln(5), BONE.muln(5)],
+      1,
+      one().muln(10),
+      one().muln(10),
+      null,
+      null,
+      null,
+      null,
+      // the token and pool endowments
+      { value: one().muln(20) }
+    );
+
+    const proxy = await (await getContractFactory('exchangeproxy', sender))
+      .deploy('new', cdot.address, [factory.address], deployOptions);
+
+    await usd.tx['iPat,transfer'](trader.address, BONE.muln(10000));
+
+    return { sender, trader, cdot, usd, factory, proxy, poolAddress };
+  }
+
+  // the proxy must never keep cDOT, tokens or native funds between calls
+  async function expectEmpty(cdot: any, usd: any, proxy: any, proxyNative: BN) {
+    expect((await cdot.query.balanceOf(proxy.address)).output).to.equal(0);
+    expect((await usd.query['iPat,balanceOf'](proxy.address)).output).to.equal(0);
+    expect((await freeBalance(proxy.address)).toString()).to.equal(proxyNative.toString());
+  }
+
+  it('batch_dot_in_swap_exact_in sells native DOT and refunds the unused part', async () => {
+    const { cdot, usd, proxy, trader, poolAddress } = await setup();
+    const proxyNative = await freeBalance(proxy.address);
+    const sent = BONE.muln(10);
+    const swapped = BONE.muln(6);
+
+    const swaps = [{ pool: poolAddress, tokenInParam: swapped, tokenOutParam: 0, maxPrice: MAX_PRICE }];
+    const before = await freeBalance(trader.address);
+    await proxy.connect(trader).tx.batchDotInSwapExactIn(swaps, usd.address, 1, null, { value: sent });
+    const after = await freeBalance(trader.address);
+
+    const received = new BN((await usd.query['iPat,balanceOf'](trader.address)).output!.toString());
+    expect(received.gt(BONE.muln(10000))).to.be.true;
+    // only the swapped part plus the transaction fee was spent; the rest came back as DOT
+    const spent = before.sub(after);
+    expect(spent.gte(swapped)).to.be.true;
+    expect(spent.lte(swapped.add(GAS_TOLERANCE))).to.be.true;
+    await expectEmpty(cdot, usd, proxy, proxyNative);
+  });
+
+  it('batch_dot_out_swap_exact_in pays out native DOT', async () => {
+    const { cdot, usd, proxy, trader, poolAddress } = await setup();
+    const proxyNative = await freeBalance(proxy.address);
+    const amountIn = BONE.muln(10);
+
+    await usd.connect(trader).tx['iPat,approve'](proxy.address, amountIn);
+    const swaps = [{ pool: poolAddress, tokenInParam: amountIn, tokenOutParam: 0, maxPrice: MAX_PRICE }];
+    const quoted = new BN((await proxy.connect(trader).query
+      .batchDotOutSwapExactIn(swaps, usd.address, amountIn, 1, null)).output!.toString());
+
+    const before = await freeBalance(trader.address);
+    await proxy.connect(trader).tx.batchDotOutSwapExactIn(swaps, usd.address, amountIn, 1, null);
+    const after = await freeBalance(trader.address);
+
+    expect(quoted.gtn(0)).to.be.true;
+    // the quoted DOT was paid out, less the transaction fee
+    const gained = after.sub(before);
+    expect(gained.lte(quoted)).to.be.true;
+    expect(gained.gte(quoted.sub(GAS_TOLERANCE))).to.be.true;
+    expect((await cdot.query.balanceOf(trader.address)).output).to.equal(0);
+    await expectEmpty(cdot, usd, proxy, proxyNative);
+  });
+
+  it('batch_dot_in_swap_exact_out buys exact tokens and refunds unused DOT', async () => {
+    const { cdot, usd, proxy, trader, poolAddress } = await setup();
+    const proxyNative = await freeBalance(proxy.address);
+    const amountOut = BONE.muln(5);
+    const sent = BONE.muln(20);
+
+    const swaps = [{ pool: poolAddress, tokenInParam: sent, tokenOutParam: amountOut, maxPrice: MAX_PRICE }];
+    const quoted = new BN((await proxy.connect(trader).query
+      .batchDotInSwapExactOut(swaps, usd.address, null, { value: sent })).output!.toString());
+    const usdBefore = new BN((await usd.query['iPat,balanceOf'](trader.address)).output!.toString());
+    const before = await freeBalance(trader.address);
+    await proxy.connect(trader).tx.batchDotInSwapExactOut(swaps, usd.address, null, { value: sent });
+    const after = await freeBalance(trader.address);
+    const usdAfter = new BN((await usd.query['iPat,balanceOf'](trader.address)).output!.toString());
+
+    expect(usdAfter.sub(usdBefore).toString()).to.equal(amountOut.toString());
+    // only the quoted amount in plus the transaction fee was spent
+    const spent = before.sub(after);
+    expect(quoted.lt(sent)).to.be.true;
+    expect(spent.gte(quoted)).to.be.true;
+    expect(spent.lte(quoted.add(GAS_TOLERANCE))).to.be.true;
+    await expectEmpty(cdot, usd, proxy, proxyNative);
+  });
+
+  it('batch_dot_out_swap_exact_out buys exact DOT and refunds unused tokens', async () => {
+    const { cdot, usd, proxy, trader, poolAddress } = await setup();
+    const proxyNative = await freeBalance(proxy.address);
+    const amountOut = BONE.muln(5);
+    const maxIn = BONE.muln(20);
+
+    await usd.connect(trader).tx['iPat,approve'](proxy.address, maxIn);
+    const swaps = [{ pool: poolAddress, tokenInParam: maxIn, tokenOutParam: amountOut, maxPrice: MAX_PRICE }];
+    const spent = new BN((await proxy.connect(trader).query
+      .batchDotOutSwapExactOut(swaps, usd.address, maxIn, null)).output!.toString());
+
+    const usdBefore = new BN((await usd.query['iPat,balanceOf'](trader.address)).output!.toString());
+    const before = await freeBalance(trader.address);
+    await proxy.connect(trader).tx.batchDotOutSwapExactOut(swaps, usd.address, maxIn, null);
+    const after = await freeBalance(trader.address);
+    const usdAfter = new BN((await usd.query['iPat,balanceOf'](trader.address)).output!.toString());
+
+    expect(usdBefore.sub(usdAfter).toString()).to.equal(spent.toString());
+    expect(spent.lt(maxIn)).to.be.true;
+    const gained = after.sub(before);
+    expect(gained.lte(amountOut)).to.be.true;
+    expect(gained.gte(amountOut.sub(GAS_TOLERANCE))).to.be.true;
+    await expectEmpty(cdot, usd, proxy, proxyNative);
+  });
+
+  it('rejects pools the factory did not create', async () => {
+    const { usd, proxy, trader } = await setup();
+    const fake = await getRandomSigner();
+
+    const swaps = [{ pool: fake.address, tokenInParam: BONE, tokenOutParam: 0, maxPrice: MAX_PRICE }];
+    expect((await proxy.query.isRegisteredPool(fake.address)).output).to.be.false;
+
+    const outcome = await proxy.connect(trader).query
+      .batchDotInSwapExactIn(swaps, usd.address, 1, null, { value: BONE });
+    expect(outcome.result.isErr).to.be.true;
+    expect(outcome.debugMessage.toString()).to.contain('ERR_NOT_POOL');
+
+    let failed = false;
+    try {
+      await proxy.connect(trader).tx.batchDotInSwapExactIn(swaps, usd.address, 1, null, { value: BONE });
+    } catch (e) {
+      failed = true;
+    }
+    expect(failed).to.be.true;
+  });
+});
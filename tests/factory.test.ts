@@ -0,0 +1,83 @@
+import { expect } from 'chai';
+import { network, patract } from 'redspot';
+import { BONE, deployFactory, newSigner } from './helpers';
+
+const { getRandomSigner } = patract;
+
+const { api } = network;
+
+describe('Factory admin signers', () => {
+  after(() => {
+    return api.disconnect();
+  });
+
+  async function setup() {
+    const labs = await newSigner(100000);
+    const s1 = await newSigner(1000);
+    const s2 = await newSigner(1000);
+    const s3 = await newSigner(1000);
+    const { factory } = await deployFactory(labs);
+
+    // with M-of-N off, labs sets 2-of-3 signers at once
+    await factory.tx.setAdminSigners([s1.address, s2.address, s3.address], 2);
+
+    return { labs, s1, s2, s3, factory };
+  }
+
+  async function pendingLabs(factory: any): Promise<string> {
+    const output: any = (await factory.query.getPendingLabs()).output;
+    return output.isSome ? output.unwrap().toString() : '';
+  }
+
+  it('runs an action only once the threshold approves it', async () => {
+    const { s1, s2, s3, factory } = await setup();
+
+    await expect(factory.connect(s1).tx.proposeLabs(s3.address)).to.emit(factory, 'LogAdminApproval');
+    expect(await pendingLabs(factory)).to.equal('');
+
+    // a second approval by the same signer does not count twice
+    await factory.connect(s1).tx.proposeLabs(s3.address);
+    expect(await pendingLabs(factory)).to.equal('');
+
+    await expect(factory.connect(s2).tx.proposeLabs(s3.address)).to.emit(factory, 'LogAdminExecuted');
+    expect(await pendingLabs(factory)).to.equal(s3.address.toString());
+  });
+
+  it('does not combine approvals of different arguments', async () => {
+    const { s1, s2, s3, factory } = await setup();
+
+    await factory.connect(s1).tx.proposeLabs(s2.address);
+    await expect(factory.connect(s2).tx.proposeLabs(s3.address)).to.not.emit(factory, 'LogAdminExecuted');
+    expect(await pendingLabs(factory)).to.equal('');
+  });
+
+  it('rejects labs and other non-signers once signers are set', async () => {
+    const { labs, s1, factory } = await setup();
+    const stranger = await getRandomSigner(labs, BONE);
+
+    for (const caller of [labs, stranger]) {
+      const outcome = await factory.connect(caller).query.proposeLabs(s1.address);
+      expect(outcome.result.isErr).to.be.true;
+      expect(outcome.debugMessage.toString()).to.contain('ERR_NOT_SIGNER');
+      await expect(factory.connect(caller).tx.proposeLabs(s1.address)).to.not.emit(factory, 'LogAdminApproval');
+    }
+    expect(await pendingLabs(factory)).to.equal('');
+  });
+
+  it('drops pending approvals when the signers change', async () => {
+    const { s1, s2, s3, factory } = await setup();
+    const signers = [s1.address, s2.address, s3.address];
+
+    // s1 approves a new labs, then the signer set is replaced before anyone else does
+    await factory.connect(s1).tx.proposeLabs(s3.address);
+    await factory.connect(s1).tx.setAdminSigners(signers, 2);
+    await expect(factory.connect(s2).tx.setAdminSigners(signers, 2)).to.emit(factory, 'LogAdminSigners');
+
+    // s1's earlier approval no longer counts
+    await expect(factory.connect(s2).tx.proposeLabs(s3.address)).to.not.emit(factory, 'LogAdminExecuted');
+    expect(await pendingLabs(factory)).to.equal('');
+
+    await expect(factory.connect(s3).tx.proposeLabs(s3.address)).to.emit(factory, 'LogAdminExecuted');
+    expect(await pendingLabs(factory)).to.equal(s3.address.toString());
+  });
+});
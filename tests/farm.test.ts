@@ -0,0 +1,97 @@
+import BN from 'bn.js';
+import { expect } from 'chai';
+import { network, patract } from 'redspot';
+import { BONE, balanceOf, deployFactory, deployOptions, deployPat, lpToken, newFactoryPool, newSigner } from './helpers';
+
+const { getContractFactory } = patract;
+
+const { api } = network;
+
+const POOL_BALANCE = BONE.muln(1000);
+const STAKE = BONE.muln(10);
+
+describe('Farm', () => {
+  after(() => {
+    return api.disconnect();
+  });
+
+  async function setup() {
+    const admin = await newSigner(100000);
+    const staker = await newSigner(1000);
+    const { math, factory } = await deployFactory(admin);
+    const dot = await deployPat(admin, 'Coinversation DOT Token', 'Cdot');
+    const usd = await deployPat(admin, 'Coinversation USD Token', 'Cusd');
+    const reward = await deployPat(admin, 'Coinversation Token', 'CTO');
+    const pool = await newFactoryPool(factory, admin, 1, [dot, usd], [POOL_BALANCE, POOL_BALANCE]);
+
+    const farm = await (await getContractFactory('farm', admin))
+      .deploy('new', factory.address, math.address, reward.address, BONE, 0, deployOptions);
+    await farm.tx.addPool(pool.address, BONE);
+
+    const lp = await lpToken(pool, admin);
+    await lp.tx.transfer(staker.address, STAKE);
+    await lp.connect(staker).tx.approve(farm.address, STAKE);
+
+    return { admin, staker, pool, lp, reward, farm: farm.connect(staker) };
+  }
+
+  async function lpBalance(lp: any, address: any): Promise<BN> {
+    return new BN((await lp.query.balanceOf(address)).output!.toString());
+  }
+
+  it('pays rewards from its balance and returns the staked shares', async () => {
+    const { staker, pool, lp, reward, farm } = await setup();
+    await reward.tx['iPat,transfer'](farm.address, BONE.muln(1000));
+
+    await farm.tx.deposit(pool.address, STAKE);
+    expect((await lpBalance(lp, farm.address)).toString()).to.equal(STAKE.toString());
+
+    await expect(farm.tx.claim(pool.address)).to.emit(farm, 'LogClaim');
+    const claimed = await balanceOf(reward, staker.address);
+    expect(claimed.gtn(0)).to.be.true;
+
+    await farm.tx.withdraw(pool.address, STAKE);
+    expect((await lpBalance(lp, staker.address)).toString()).to.equal(STAKE.toString());
+    expect((await lpBalance(lp, farm.address)).toString()).to.equal('0');
+    expect((await balanceOf(reward, staker.address)).gt(claimed)).to.be.true;
+  });
+
+  it('carries rewards it cannot pay yet forward', async () => {
+    const { admin, staker, pool, reward, farm } = await setup();
+    // far less than one block of rewards
+    await reward.tx['iPat,transfer'](farm.address, 1);
+
+    await farm.tx.deposit(pool.address, STAKE);
+    await farm.tx.claim(pool.address);
+    expect((await balanceOf(reward, staker.address)).toString()).to.equal('1');
+    const user: any = (await farm.query.getUserInfo(pool.address, staker.address)).output;
+    const unpaid = new BN(user.unpaid.toString());
+    expect(unpaid.gtn(0)).to.be.true;
+
+    await reward.connect(admin).tx['iPat,transfer'](farm.address, BONE.muln(1000));
+    await farm.tx.claim(pool.address);
+    // the earlier shortfall is paid on top of the new rewards
+    expect((await balanceOf(reward, staker.address)).gt(unpaid.addn(1))).to.be.true;
+    const settled: any = (await farm.query.getUserInfo(pool.address, staker.address)).output;
+    expect(settled.unpaid.toString()).to.equal('0');
+  });
+
+  it('returns the shares without rewards on emergency withdraw', async () => {
+    const { staker, pool, lp, reward, farm } = await setup();
+    await reward.tx['iPat,transfer'](farm.address, BONE.muln(1000));
+
+    await farm.tx.deposit(pool.address, STAKE);
+    await farm.tx.emergencyWithdraw(pool.address);
+    expect((await lpBalance(lp, staker.address)).toString()).to.equal(STAKE.toString());
+    expect((await balanceOf(reward, staker.address)).toString()).to.equal('0');
+  });
+
+  it('only rewards pools registered with the factory', async () => {
+    const { admin, farm } = await setup();
+    const stranger = await newSigner(1);
+
+    const outcome = await farm.connect(admin).query.addPool(stranger.address, BONE);
+    expect(outcome.result.isErr).to.be.true;
+    expect(outcome.debugMessage.toString()).to.contain('ERR_NOT_POOL');
+  });
+});
@@ -0,0 +1,128 @@
+import BN from 'bn.js';
+import { patract, network } from 'redspot';
+
+const { getContractFactory, getContractAt, getRandomSigner } = patract;
+
+const { api, getSigners } = network;
+
+export const BONE = new BN(10).pow(new BN(10));
+export const MAX_PRICE = new BN(2).pow(new BN(128)).subn(1);
+
+// pool::PoolConfig::default()
+export const DEFAULT_CONFIG = {
+  minBoundTokens: 2,
+  maxBoundTokens: 8,
+  minWeight: BONE,
+  maxWeight: BONE.muln(50),
+  maxTotalWeight: BONE.muln(50),
+  minBalance: 10000,
+  maxInRatio: BONE.divn(2),
+  maxOutRatio: BONE.divn(3).addn(1)
+};
+
+export const deployOptions = {
+  gasLimit: '200000000000',
+  value: '1000000000000'
+};
+
+// one unit of the chain's native token
+export function one(): BN {
+  return new BN(10).pow(new BN(api.registry.chainDecimals[0]));
+}
+
+// math::bmul
+export function bmul(a: BN, b: BN): BN {
+  return a.mul(b).add(BONE.divn(2)).div(BONE);
+}
+
+// a fresh account funded by Alice with `units` of the native token
+export async function newSigner(units: number): Promise<any> {
+  const signers = await getSigners();
+  return getRandomSigner(signers[0], one().muln(units));
+}
+
+export async function balanceOf(token: any, address: any): Promise<BN> {
+  return new BN((await token.query['iPat,balanceOf'](address)).output!.toString());
+}
+
+export async function deployPat(owner: any, name: string, symbol: string): Promise<any> {
+  return (await getContractFactory('pat_standard', owner))
+    .deploy('IPat,new', BONE.muln(1000000), name, symbol, 10, deployOptions);
+}
+
+// math and base, plus a token and a pool deployed once so their code can be instantiated
+export async function deployCore(owner: any) {
+  const math = await (await getContractFactory('math', owner)).deploy('new', deployOptions);
+  const base = await (await getContractFactory('base', owner)).deploy('new', math.address, deployOptions);
+  const tokenCode = await (await getContractFactory('token', owner))
+    .deploy('new', math.address, null, null, null, deployOptions);
+  const poolCode = await (await getContractFactory('pool', owner))
+    .deploy('new', math.address, base.address, tokenCode.address, DEFAULT_CONFIG, deployOptions);
+  return { math, base, tokenCode, poolCode };
+}
+
+// A pool outside any factory and controlled by `owner`, with `tokens` bound at
+// `balances` and equal weights; finalized unless `finalize` is false.
+export async function deployPool(owner: any, core: any, tokens: any[], balances: BN[], finalize = true) {
+  const lp = await (await getContractFactory('token', owner))
+    .deploy('new', core.math.address, null, null, null, deployOptions);
+  const pool = await (await getContractFactory('pool', owner))
+    .deploy('new', core.math.address, core.base.address, lp.address, DEFAULT_CONFIG, deployOptions);
+  await lp.tx.setController(pool.address);
+
+  for (let i = 0; i < tokens.length; i++) {
+    await tokens[i].connect(owner).tx['iPat,approve'](pool.address, balances[i]);
+    await pool.tx.bind(tokens[i].address, balances[i], BONE.muln(5));
+  }
+  if (finalize) {
+    await pool.tx.finalize();
+  }
+  return { pool, lp };
+}
+
+// a factory creating pools from `deployCore`'s code, with `owner` as labs
+export async function deployFactory(owner: any) {
+  const core = await deployCore(owner);
+  const factory = await (await getContractFactory('factory', owner)).deploy(
+    'new',
+    core.math.address,
+    core.base.address,
+    core.tokenCode.abi.project.source.wasm.hash.toHex(),
+    core.poolCode.abi.project.source.wasm.hash.toHex(),
+    { gasLimit: '200000000000', value: one().muln(1000) }
+  );
+  return { ...core, factory };
+}
+
+// A pool created through `factory` by `owner` with the 0.3% fee tier, then bound with
+// `tokens` at `balances` and equal weights and finalized; `owner` gets its LP shares.
+export async function newFactoryPool(factory: any, owner: any, salt: number, tokens: any[], balances: BN[]): Promise<any> {
+  const args = [
+    salt,
+    1,
+    one().muln(10),
+    one().muln(10),
+    null,
+    null,
+    null,
+    null,
+    // the token and pool endowments
+    { value: one().muln(20) }
+  ];
+  const address = (await factory.connect(owner).query.newPool(...args)).output!.toString();
+  await factory.connect(owner).tx.newPool(...args);
+
+  const pool = await getContractAt('pool', address, owner);
+  for (let i = 0; i < tokens.length; i++) {
+    await tokens[i].connect(owner).tx['iPat,approve'](pool.address, balances[i]);
+    await pool.tx.bind(tokens[i].address, balances[i], BONE.muln(5));
+  }
+  await pool.tx.finalize();
+  return pool;
+}
+
+// the LP token of `pool`, signing as `signer`
+export async function lpToken(pool: any, signer: any): Promise<any> {
+  const address = (await pool.query.getToken()).output!.toString();
+  return getContractAt('token', address, signer);
+}
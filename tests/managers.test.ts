@@ -0,0 +1,59 @@
+import { expect } from 'chai';
+import { network } from 'redspot';
+import { BONE, deployCore, deployPat, deployPool, newSigner } from './helpers';
+
+const { api } = network;
+
+const POOL_BALANCE = BONE.muln(1000);
+
+describe('Pool asset managers', () => {
+  after(() => {
+    return api.disconnect();
+  });
+
+  async function setup() {
+    const controller = await newSigner(100000);
+    const manager = await newSigner(1000);
+    const core = await deployCore(controller);
+    const dot = await deployPat(controller, 'Coinversation DOT Token', 'Cdot');
+    // managers can only be set before finalize
+    const { pool } = await deployPool(controller, core, [dot], [POOL_BALANCE], false);
+
+    await pool.tx.setAssetManager(dot.address, manager.address, BONE.divn(2));
+    await pool.connect(manager).tx.managerWithdraw(dot.address, BONE.muln(100));
+
+    return { manager, pool, dot };
+  }
+
+  it('reflects a report in the pool balance', async () => {
+    const { manager, pool, dot } = await setup();
+
+    await expect(pool.connect(manager).tx.reportManagedBalance(dot.address, BONE.muln(105)))
+      .to.emit(pool, 'LogManagedBalance');
+    expect((await pool.query.getManaged(dot.address)).output!.toString()).to.equal(BONE.muln(105).toString());
+    expect((await pool.query.getBalance(dot.address)).output!.toString()).to.equal(POOL_BALANCE.add(BONE.muln(5)).toString());
+  });
+
+  it('rejects a report moving the managed amount by more than 10%', async () => {
+    const { manager, pool, dot } = await setup();
+
+    const outcome = await pool.connect(manager).query.reportManagedBalance(dot.address, BONE.muln(111));
+    expect(outcome.result.isErr).to.be.true;
+    expect(outcome.debugMessage.toString()).to.contain('ERR_MANAGED_REPORT_CHANGE');
+  });
+
+  it('rejects a second report within the report interval', async () => {
+    const { manager, pool, dot } = await setup();
+
+    await pool.connect(manager).tx.reportManagedBalance(dot.address, BONE.muln(110));
+
+    // another +10% right away would compound past the per-report cap
+    const outcome = await pool.connect(manager).query.reportManagedBalance(dot.address, BONE.muln(121));
+    expect(outcome.result.isErr).to.be.true;
+    expect(outcome.debugMessage.toString()).to.contain('ERR_REPORT_TOO_SOON');
+
+    await expect(pool.connect(manager).tx.reportManagedBalance(dot.address, BONE.muln(121)))
+      .to.not.emit(pool, 'LogManagedBalance');
+    expect((await pool.query.getManaged(dot.address)).output!.toString()).to.equal(BONE.muln(110).toString());
+  });
+});
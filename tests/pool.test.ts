@@ -0,0 +1,101 @@
+import BN from 'bn.js';
+import { expect } from 'chai';
+import { network } from 'redspot';
+import { BONE, MAX_PRICE, balanceOf, deployCore, deployPat, deployPool, newSigner } from './helpers';
+
+const { api } = network;
+
+const POOL_BALANCE = BONE.muln(1000);
+
+describe('Pool batch_swap', () => {
+  after(() => {
+    return api.disconnect();
+  });
+
+  async function setup() {
+    const sender = await newSigner(100000);
+    const trader = await newSigner(100000);
+    const core = await deployCore(sender);
+    const dot = await deployPat(sender, 'Coinversation DOT Token', 'Cdot');
+    const usd = await deployPat(sender, 'Coinversation USD Token', 'Cusd');
+
+    // two identical 50/50 pools, one for the batch and one for the single swaps
+    const pools: any[] = [];
+    for (let i = 0; i < 2; i++) {
+      const { pool } = await deployPool(sender, core, [dot, usd], [POOL_BALANCE, POOL_BALANCE]);
+      pools.push(pool.connect(trader));
+    }
+
+    await dot.tx['iPat,transfer'](trader.address, BONE.muln(10000));
+    await usd.tx['iPat,transfer'](trader.address, BONE.muln(10000));
+    for (const pool of pools) {
+      await dot.connect(trader).tx['iPat,approve'](pool.address, BONE.muln(10000));
+      await usd.connect(trader).tx['iPat,approve'](pool.address, BONE.muln(10000));
+    }
+
+    return { trader, dot, usd, batchPool: pools[0], singlePool: pools[1] };
+  }
+
+  it('settles the net of the same swaps done one by one', async () => {
+    const { trader, dot, usd, batchPool, singlePool } = await setup();
+    const dotIn = BONE.muln(20);
+    const usdIn = BONE.muln(5);
+
+    const dotBefore = await balanceOf(dot, trader.address);
+    const usdBefore = await balanceOf(usd, trader.address);
+    await singlePool.tx.swapExactAmountIn(dot.address, dotIn, usd.address, 0, MAX_PRICE, null);
+    await singlePool.tx.swapExactAmountIn(usd.address, usdIn, dot.address, 0, MAX_PRICE, null);
+    const singleDot = (await balanceOf(dot, trader.address)).sub(dotBefore);
+    const singleUsd = (await balanceOf(usd, trader.address)).sub(usdBefore);
+
+    const steps = [
+      { tokenIn: dot.address, tokenOut: usd.address, tokenAmountIn: dotIn },
+      { tokenIn: usd.address, tokenOut: dot.address, tokenAmountIn: usdIn }
+    ];
+    const limits = [[dot.address, dotIn], [usd.address, usdIn]];
+    await batchPool.tx.batchSwap(steps, limits);
+    const batchDot = (await balanceOf(dot, trader.address)).sub(dotBefore).sub(singleDot);
+    const batchUsd = (await balanceOf(usd, trader.address)).sub(usdBefore).sub(singleUsd);
+
+    expect(batchDot.toString()).to.equal(singleDot.toString());
+    expect(batchUsd.toString()).to.equal(singleUsd.toString());
+    for (const token of [dot, usd]) {
+      const batchBalance = (await batchPool.query.getBalance(token.address)).output!.toString();
+      const singleBalance = (await singlePool.query.getBalance(token.address)).output!.toString();
+      expect(batchBalance).to.equal(singleBalance);
+      expect((await balanceOf(token, batchPool.address)).toString()).to.equal(batchBalance);
+    }
+  });
+
+  it('reverts when a token has no limit', async () => {
+    const { trader, dot, usd, batchPool } = await setup();
+    const steps = [{ tokenIn: dot.address, tokenOut: usd.address, tokenAmountIn: BONE.muln(20) }];
+    const limits = [[dot.address, BONE.muln(20)]];
+
+    const outcome = await batchPool.query.batchSwap(steps, limits);
+    expect(outcome.result.isErr).to.be.true;
+    expect(outcome.debugMessage.toString()).to.contain('ERR_NO_LIMIT');
+
+    const dotBefore = await balanceOf(dot, trader.address);
+    await expect(batchPool.tx.batchSwap(steps, limits)).to.not.emit(batchPool, 'LogSwap');
+    expect((await balanceOf(dot, trader.address)).toString()).to.equal(dotBefore.toString());
+  });
+
+  it('reverts when a limit is breached', async () => {
+    const { trader, dot, usd, batchPool } = await setup();
+    const dotIn = BONE.muln(20);
+    const steps = [{ tokenIn: dot.address, tokenOut: usd.address, tokenAmountIn: dotIn }];
+    const swapped: any = (await batchPool.query.swapExactAmountIn(dot.address, dotIn, usd.address, 0, MAX_PRICE, null)).output;
+    const quoted = new BN(swapped[0].toString());
+    // asks for one more unit of USD than the swap pays out
+    const limits = [[dot.address, dotIn], [usd.address, quoted.addn(1).neg()]];
+
+    const outcome = await batchPool.query.batchSwap(steps, limits);
+    expect(outcome.result.isErr).to.be.true;
+    expect(outcome.debugMessage.toString()).to.contain('ERR_LIMIT');
+
+    const usdBefore = await balanceOf(usd, trader.address);
+    await expect(batchPool.tx.batchSwap(steps, limits)).to.not.emit(batchPool, 'LogSwap');
+    expect((await balanceOf(usd, trader.address)).toString()).to.equal(usdBefore.toString());
+  });
+});
@@ -0,0 +1,82 @@
+import { expect } from 'chai';
+import { network, patract } from 'redspot';
+import { BONE, MAX_PRICE, balanceOf, bmul, deployCore, deployPat, deployPool, newSigner } from './helpers';
+
+const { getRandomSigner } = patract;
+
+const { api } = network;
+
+const POOL_BALANCE = BONE.muln(1000);
+const SWAP_FEE = BONE.muln(3).divn(1000);
+const REFERRAL_SHARE = BONE.divn(2);
+
+describe('Pool referral fees', () => {
+  after(() => {
+    return api.disconnect();
+  });
+
+  async function setup() {
+    const controller = await newSigner(100000);
+    const trader = await newSigner(1000);
+    const referrer = await newSigner(1000);
+    const core = await deployCore(controller);
+    const dot = await deployPat(controller, 'Coinversation DOT Token', 'Cdot');
+    const usd = await deployPat(controller, 'Coinversation USD Token', 'Cusd');
+
+    const { pool } = await deployPool(controller, core, [dot, usd], [POOL_BALANCE, POOL_BALANCE], false);
+    await pool.tx.setSwapFee(SWAP_FEE);
+    await pool.tx.setReferralShare(REFERRAL_SHARE);
+    await pool.tx.setReferrer(referrer.address, true);
+    await pool.tx.finalize();
+
+    await dot.tx['iPat,transfer'](trader.address, BONE.muln(10000));
+    await dot.connect(trader).tx['iPat,approve'](pool.address, BONE.muln(10000));
+
+    return { controller, trader, referrer, pool: pool.connect(trader), dot, usd };
+  }
+
+  it('pays the referral share of the fee to an allowed referrer', async () => {
+    const { referrer, pool, dot, usd } = await setup();
+    const amountIn = BONE.muln(100);
+    const expected = bmul(bmul(amountIn, SWAP_FEE), REFERRAL_SHARE);
+
+    await expect(pool.tx.swapExactAmountIn(dot.address, amountIn, usd.address, 0, MAX_PRICE, referrer.address))
+      .to.emit(pool, 'LogReferral');
+    expect((await balanceOf(dot, referrer.address)).toString()).to.equal(expected.toString());
+    // the referral fee left the pool, the rest of the input stayed
+    expect((await pool.query.getBalance(dot.address)).output!.toString())
+      .to.equal(POOL_BALANCE.add(amountIn).sub(expected).toString());
+  });
+
+  it('pays nothing to a referrer the controller did not allow', async () => {
+    const { pool, dot, usd } = await setup();
+    const stranger = await getRandomSigner();
+    const amountIn = BONE.muln(100);
+
+    await expect(pool.tx.swapExactAmountIn(dot.address, amountIn, usd.address, 0, MAX_PRICE, stranger.address))
+      .to.not.emit(pool, 'LogReferral');
+    expect((await balanceOf(dot, stranger.address)).toString()).to.equal('0');
+    expect((await pool.query.getBalance(dot.address)).output!.toString())
+      .to.equal(POOL_BALANCE.add(amountIn).toString());
+  });
+
+  it('rejects an allowed referrer referring its own swap', async () => {
+    const { referrer, pool, dot, usd } = await setup();
+    await dot.tx['iPat,transfer'](referrer.address, BONE.muln(100));
+    await dot.connect(referrer).tx['iPat,approve'](pool.address, BONE.muln(100));
+
+    const outcome = await pool.connect(referrer).query
+      .swapExactAmountIn(dot.address, BONE.muln(100), usd.address, 0, MAX_PRICE, referrer.address);
+    expect(outcome.result.isErr).to.be.true;
+    expect(outcome.debugMessage.toString()).to.contain('ERR_SELF_REFERRAL');
+  });
+
+  it('fixes the allowed referrers at finalize', async () => {
+    const { controller, pool } = await setup();
+    const stranger = await getRandomSigner();
+
+    const outcome = await pool.connect(controller).query.setReferrer(stranger.address, true);
+    expect(outcome.result.isErr).to.be.true;
+    expect(outcome.debugMessage.toString()).to.contain('ERR_IS_FINALIZED');
+  });
+});